@@ -0,0 +1,339 @@
+// Bitboards and the attack tables of the pieces
+//
+// A bitboard holds one bit for each tile, numbered like the tiles of Square: bit 0 is a8,
+// bit 7 is h8 and bit 63 is h1
+
+use std::sync::OnceLock;
+
+pub(crate) type Bitboard = u64;
+
+// Tiles where a8 is light, so (row + column) is even
+pub(crate) const LIGHT_TILES: Bitboard = 0xaa55_aa55_aa55_aa55;
+
+// (row, column) steps of the pieces, rows counting from rank 8 down to rank 1
+const KING_STEPS: [(isize, isize); 8] = [
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+];
+const KNIGHT_STEPS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+];
+// Pawns attack towards the other side of the board, white pawns towards rank 8
+const WHITE_PAWN_STEPS: [(isize, isize); 2] = [(-1, -1), (-1, 1)];
+const BLACK_PAWN_STEPS: [(isize, isize); 2] = [(1, -1), (1, 1)];
+
+pub(crate) const KING_ATTACKS: [Bitboard; 64] = step_attacks(&KING_STEPS);
+pub(crate) const KNIGHT_ATTACKS: [Bitboard; 64] = step_attacks(&KNIGHT_STEPS);
+// Indexed by the color of the pawn, white first
+pub(crate) const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    step_attacks(&WHITE_PAWN_STEPS),
+    step_attacks(&BLACK_PAWN_STEPS),
+];
+
+// The rays of the sliding pieces, in the order of KING_STEPS. Odd directions are diagonal
+const RAYS: [[Bitboard; 64]; 8] = rays();
+
+// Get the bitboard of a single tile
+pub(crate) fn bit(index: usize) -> Bitboard {
+    1 << index
+}
+
+// Iterate over the tiles of a bitboard, lowest first
+pub(crate) fn tiles(bitboard: Bitboard) -> Tiles {
+    Tiles(bitboard)
+}
+
+pub(crate) struct Tiles(Bitboard);
+
+impl Iterator for Tiles {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(index)
+    }
+}
+
+// Get the tiles a rook on a tile attacks, up to and including the first piece on each line
+pub(crate) fn rook_attacks(index: usize, occupied: Bitboard) -> Bitboard {
+    let tables = sliding_tables();
+    tables.attacks[tables.rooks[index].offset(occupied)]
+}
+
+// Get the tiles a bishop on a tile attacks, up to and including the first piece on each line
+pub(crate) fn bishop_attacks(index: usize, occupied: Bitboard) -> Bitboard {
+    let tables = sliding_tables();
+    tables.attacks[tables.bishops[index].offset(occupied)]
+}
+
+// Get the tiles strictly between two tiles on the same line, or nothing when they are not on
+// a line
+pub(crate) fn between(from: usize, to: usize) -> Bitboard {
+    let (from_tile, to_tile) = (bit(from), bit(to));
+    if rook_attacks(from, 0) & to_tile != 0 {
+        rook_attacks(from, to_tile) & rook_attacks(to, from_tile)
+    } else if bishop_attacks(from, 0) & to_tile != 0 {
+        bishop_attacks(from, to_tile) & bishop_attacks(to, from_tile)
+    } else {
+        0
+    }
+}
+
+// Sliding attacks are looked up with magic bitboards. Only the pieces on the lines of a slider
+// matter, leaving out the tiles at the edge of the board since nothing lies behind them.
+// Multiplying those pieces by a magic number gathers them in the top bits, which then index a
+// table holding the attacks for that combination of pieces
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    start: usize,
+}
+
+impl Magic {
+    fn offset(&self, occupied: Bitboard) -> usize {
+        self.start + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct SlidingTables {
+    rooks: Vec<Magic>,
+    bishops: Vec<Magic>,
+    attacks: Vec<Bitboard>,
+}
+
+// The tables are found once, the first time a slider's attacks are needed
+fn sliding_tables() -> &'static SlidingTables {
+    static TABLES: OnceLock<SlidingTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut attacks = Vec::new();
+        let rooks = (0..64)
+            .map(|index| find_magic(index, &ROOK_DIRECTIONS, &mut attacks))
+            .collect();
+        let bishops = (0..64)
+            .map(|index| find_magic(index, &BISHOP_DIRECTIONS, &mut attacks))
+            .collect();
+        SlidingTables {
+            rooks,
+            bishops,
+            attacks,
+        }
+    })
+}
+
+// Try random sparse numbers until one sends every combination of pieces to a table entry
+// that holds no other attacks, then add the table to the attacks
+fn find_magic(index: usize, directions: &[usize], attacks: &mut Vec<Bitboard>) -> Magic {
+    let mask = directions.iter().fold(0, |mask, direction| {
+        let ray = RAYS[*direction][index];
+        // The last tile of a ray is the one with nothing behind it
+        let edge = tiles(ray)
+            .filter(|tile| RAYS[*direction][*tile] == 0)
+            .fold(0, |edge, tile| edge | bit(tile));
+        mask | ray & !edge
+    });
+    let bits = mask.count_ones();
+
+    // Walk through every subset of the mask
+    let mut subsets = Vec::with_capacity(1 << bits);
+    let mut subset: Bitboard = 0;
+    loop {
+        subsets.push((subset, slide(index, subset, directions)));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+
+    // Entries are marked with the attempt that filled them, so the table need not be cleared
+    let mut table: Vec<(usize, Bitboard)> = vec![(0, 0); 1 << bits];
+    let mut random = Random(MAGIC_SEEDS[index / 8]);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let magic = random.next() & random.next() & random.next();
+        // Magics that leave too few bits at the top cannot work
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+        let candidate = Magic {
+            mask,
+            magic,
+            shift: 64 - bits,
+            start: 0,
+        };
+        let fits = subsets.iter().all(|(occupied, attack)| {
+            let entry = &mut table[candidate.offset(*occupied)];
+            if entry.0 == attempt {
+                entry.1 == *attack
+            } else {
+                *entry = (attempt, *attack);
+                true
+            }
+        });
+        if fits {
+            let start = attacks.len();
+            attacks.extend(table.iter().map(|entry| entry.1));
+            return Magic { start, ..candidate };
+        }
+    }
+}
+
+// Fixed seeds for each row find the same magics on every run, and were picked because they
+// find them quickly
+const MAGIC_SEEDS: [u64; 8] = [83, 33, 110, 43, 79, 199, 169, 255];
+
+const ROOK_DIRECTIONS: [usize; 4] = [0, 2, 4, 6];
+const BISHOP_DIRECTIONS: [usize; 4] = [1, 3, 5, 7];
+
+// Get the attacks of a slider by walking its lines, for filling in the magic tables
+fn slide(index: usize, occupied: Bitboard, directions: &[usize]) -> Bitboard {
+    directions
+        .iter()
+        .fold(0, |attacks, direction| attacks | ray_attacks(index, occupied, *direction))
+}
+
+// Xorshift with a multiplied output, which is enough for finding magics
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+// Cut a ray off behind the first piece on it. Rays pointing up the board go to lower tiles,
+// so their nearest piece is the highest bit
+fn ray_attacks(index: usize, occupied: Bitboard, direction: usize) -> Bitboard {
+    let ray = RAYS[direction][index];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let (row, column) = KING_STEPS[direction];
+    let blocker = if row * 8 + column < 0 {
+        63 - blockers.leading_zeros() as usize
+    } else {
+        blockers.trailing_zeros() as usize
+    };
+    ray & !RAYS[direction][blocker]
+}
+
+// Get the tile a number of rows and columns away, if it is on the board
+const fn step(index: usize, row: isize, column: isize) -> Option<usize> {
+    let new_row = (index / 8) as isize + row;
+    let new_column = (index % 8) as isize + column;
+    if new_row >= 0 && new_row < 8 && new_column >= 0 && new_column < 8 {
+        Some((new_row * 8 + new_column) as usize)
+    } else {
+        None
+    }
+}
+
+const fn step_attacks(steps: &[(isize, isize)]) -> [Bitboard; 64] {
+    let mut attacks = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let mut i = 0;
+        while i < steps.len() {
+            if let Some(target) = step(index, steps[i].0, steps[i].1) {
+                attacks[index] |= 1 << target;
+            }
+            i += 1;
+        }
+        index += 1;
+    }
+    attacks
+}
+
+const fn rays() -> [[Bitboard; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let (row, column) = KING_STEPS[direction];
+        let mut index = 0;
+        while index < 64 {
+            let mut tile = step(index, row, column);
+            while let Some(current) = tile {
+                rays[direction][index] |= 1 << current;
+                tile = step(current, row, column);
+            }
+            index += 1;
+        }
+        direction += 1;
+    }
+    rays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attack_tables() {
+        // Knight on a8 and king on h1 stay on the board
+        assert_eq!(tiles(KNIGHT_ATTACKS[0]).collect::<Vec<_>>(), vec![10, 17]);
+        assert_eq!(tiles(KING_ATTACKS[63]).collect::<Vec<_>>(), vec![54, 55, 62]);
+        // A white pawn on e2 attacks d3 and f3, a black pawn on a7 only b6
+        assert_eq!(tiles(PAWN_ATTACKS[0][52]).collect::<Vec<_>>(), vec![43, 45]);
+        assert_eq!(tiles(PAWN_ATTACKS[1][8]).collect::<Vec<_>>(), vec![17]);
+    }
+
+    #[test]
+    fn test_sliding_attacks() {
+        // A rook on d4 with pieces on d6 and b4 sees up to them
+        let occupied = bit(19) | bit(33);
+        let attacks = rook_attacks(35, occupied);
+        assert_eq!(attacks.count_ones(), 2 + 4 + 3 + 2);
+        assert_ne!(attacks & bit(19), 0);
+        assert_eq!(attacks & bit(11), 0);
+        assert_ne!(attacks & bit(33), 0);
+        assert_eq!(attacks & bit(32), 0);
+        // A bishop on a8 sees the whole long diagonal on an empty board
+        assert_eq!(bishop_attacks(0, 0).count_ones(), 7);
+        assert_eq!(bishop_attacks(0, bit(27)).count_ones(), 3);
+    }
+
+    #[test]
+    fn test_magic_attacks() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            let occupied = random.next() & random.next();
+            for index in 0..64 {
+                assert_eq!(
+                    rook_attacks(index, occupied),
+                    slide(index, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    bishop_attacks(index, occupied),
+                    slide(index, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+        // The masks leave out the edges, giving the usual table sizes
+        let tables = sliding_tables();
+        assert_eq!(tables.rooks[0].mask.count_ones(), 12);
+        assert_eq!(tables.bishops[27].mask.count_ones(), 9);
+        assert_eq!(tables.attacks.len(), 102_400 + 5_248);
+    }
+}
@@ -1,5 +1,20 @@
 // Chess library
-use std::iter::Iterator;
+use std::error::Error;
+use std::fmt;
+
+mod bitboard;
+mod pgn;
+mod san;
+mod tree;
+mod types;
+mod zobrist;
+
+pub use pgn::{PgnError, PgnReader, PgnTags};
+pub use tree::{GameNode, GameTree};
+pub use types::{Color, Move, MoveFlags, Piece, PieceKind, Square};
+
+use bitboard::{Bitboard, KING_ATTACKS, KNIGHT_ATTACKS, LIGHT_TILES, PAWN_ATTACKS};
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 
 // GameState represents the current state of the game
@@ -8,67 +23,280 @@ pub enum GameState {
     Check,
     GameOver,
     Checkmate, // DeadPosition
+    Stalemate,
+    Draw(DrawReason),
 }
 
-enum ColorState {
-    White,
-    Black,
+// DrawReason tells which rule drew the game. The fifty-move rule and threefold repetition
+// only allow a player to claim a draw, so moves may still be made after them. The others end
+// the game
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    InsufficientMaterial,
 }
 
-enum Castling {
+// A right to castle, named after the side of the board the king castles to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Castling {
     BlackKing,
     BlackQueen,
     WhiteKing,
     WhiteQueen,
 }
 
+impl Castling {
+    // Color of the player holding the right
+    fn color(self) -> Color {
+        match self {
+            Castling::WhiteKing | Castling::WhiteQueen => Color::White,
+            Castling::BlackKing | Castling::BlackQueen => Color::Black,
+        }
+    }
+
+    // Tile the king starts on
+    fn king_tile(self) -> usize {
+        match self.color() {
+            Color::White => 60,
+            Color::Black => 4,
+        }
+    }
+
+    // Tile the king lands on
+    fn king_target(self) -> usize {
+        match self {
+            Castling::WhiteKing => 62,
+            Castling::WhiteQueen => 58,
+            Castling::BlackKing => 6,
+            Castling::BlackQueen => 2,
+        }
+    }
+
+    // Tile the rook starts on
+    fn rook_tile(self) -> usize {
+        match self {
+            Castling::WhiteKing => 63,
+            Castling::WhiteQueen => 56,
+            Castling::BlackKing => 7,
+            Castling::BlackQueen => 0,
+        }
+    }
+
+    // Tile the rook lands on, which is the one the king passes
+    fn rook_target(self) -> usize {
+        (self.king_tile() + self.king_target()) / 2
+    }
+}
+
+// FenError describes why a FEN string could not be turned into a game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    InvalidPiece(char),
+    WrongRankCount(usize),
+    InvalidRankLength(usize),
+    InvalidTurn(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(count) => {
+                write!(f, "expected 6 FEN fields, found {}", count)
+            }
+            FenError::InvalidPiece(piece) => write!(f, "invalid piece '{}' in FEN", piece),
+            FenError::WrongRankCount(count) => {
+                write!(f, "expected 8 ranks in FEN board, found {}", count)
+            }
+            FenError::InvalidRankLength(rank) => {
+                write!(f, "rank {} in FEN board does not describe 8 tiles", rank)
+            }
+            FenError::InvalidTurn(turn) => write!(f, "invalid side to move '{}' in FEN", turn),
+            FenError::InvalidCastling(castling) => {
+                write!(f, "invalid castling availability '{}' in FEN", castling)
+            }
+            FenError::InvalidEnPassant(square) => {
+                write!(f, "invalid en passant square '{}' in FEN", square)
+            }
+            FenError::InvalidHalfmoveClock(clock) => {
+                write!(f, "invalid halfmove clock '{}' in FEN", clock)
+            }
+            FenError::InvalidFullmoveNumber(number) => {
+                write!(f, "invalid fullmove number '{}' in FEN", number)
+            }
+        }
+    }
+}
+
+impl Error for FenError {}
+
+// ChessError describes why an operation on a game was rejected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChessError {
+    InvalidSquare(String),
+    InvalidPiece(String),
+    InvalidColor(String),
+    InvalidMove(String),
+    NotYourTurn,
+    NoPieceOnSquare,
+    IllegalMove,
+    AmbiguousMove(String),
+    GameOver,
+    InvalidPromotion(PieceKind),
+    MalformedFen(FenError),
+    InvalidComment(String),
+    InvalidResult(String),
+}
+
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChessError::InvalidSquare(square) => write!(f, "'{}' is not a square", square),
+            ChessError::InvalidPiece(piece) => write!(f, "'{}' is not a piece", piece),
+            ChessError::InvalidColor(color) => write!(f, "'{}' is not a color", color),
+            ChessError::InvalidMove(notation) => write!(f, "'{}' is not a move", notation),
+            ChessError::NotYourTurn => write!(f, "piece belongs to the player not in turn"),
+            ChessError::NoPieceOnSquare => write!(f, "there is no piece on the square"),
+            ChessError::IllegalMove => write!(f, "move is not legal"),
+            ChessError::AmbiguousMove(notation) => {
+                write!(f, "'{}' could be more than one move", notation)
+            }
+            ChessError::GameOver => write!(f, "game is already over"),
+            ChessError::InvalidPromotion(kind) => {
+                write!(f, "a pawn can not promote to '{}'", kind)
+            }
+            ChessError::MalformedFen(error) => write!(f, "malformed FEN: {}", error),
+            ChessError::InvalidComment(comment) => {
+                write!(f, "'{}' can not be written as a PGN comment", comment)
+            }
+            ChessError::InvalidResult(result) => write!(f, "'{}' is not a game result", result),
+        }
+    }
+}
+
+impl Error for ChessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChessError::MalformedFen(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FenError> for ChessError {
+    fn from(error: FenError) -> ChessError {
+        ChessError::MalformedFen(error)
+    }
+}
+
+// A move that has been played, with what is needed to take it back: the piece it captured and
+// the castling rights, en passant target and clocks from before the move
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub mv: Move,
+    pub captured: Option<Piece>,
+    pub castling: Vec<Castling>,
+    pub enpassant: Option<Square>,
+    pub halfmove: u32,
+    pub fullmove: u32,
+}
+
+// What make_move changed that unmake_move cannot work out from the board
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UndoInfo {
+    captured: Option<Piece>,
+    castling: Vec<Castling>,
+    enpassant: Option<Square>,
+    halfmove: u32,
+    fullmove: u32,
+    state: GameState,
+    winner: Option<Color>,
+    hash: u64,
+}
+
+// Pieces a pawn may promote to
+const PROMOTION_KINDS: [PieceKind; 4] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+];
+
 //
+#[derive(Clone)]
 pub struct Game {
-    board: Vec<u8>,
+    // Bitboards of each kind of piece for each color, and of all pieces of each color
+    pieces: [[Bitboard; 6]; 2],
+    colors: [Bitboard; 2],
     state: GameState,
-    turn: ColorState,
+    winner: Option<Color>,
+    turn: Color,
     castling: Vec<Castling>,
-    promotion: u8,
-    enpassant: String,
+    enpassant: Option<Square>,
+    halfmove: u32,
+    fullmove: u32,
+    // Zobrist key of the position, and the repetition keys of it and the positions before it
+    hash: u64,
+    positions: Vec<u64>,
+    history: Vec<HistoryEntry>,
+    undone: Vec<Move>,
+    // FEN of the position the game started from, unless it was the standard one
+    start: Option<String>,
+}
+
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
+    }
 }
 
 impl Game {
     // Create new standard board
-    fn new() -> Game {
-        let mut game = Game {
-            board: Vec::new(),
-            state: GameState::InProgress,
-            turn: ColorState::White,
-            castling: vec![
-                Castling::WhiteKing,
-                Castling::WhiteQueen,
-                Castling::BlackKing,
-                Castling::BlackQueen,
-            ],
-            promotion: 0b0000_0110,
-            enpassant: "-".to_string(),
-        };
-        game.apply_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-        game
+    pub fn new() -> Game {
+        Game::from_fen(STARTING_FEN).expect("standard FEN is valid")
     }
 
-    // Create custom board
-    fn new_board(fen: &str) -> Game {
+    // Create custom board from a FEN string
+    pub fn from_fen(fen: &str) -> Result<Game, FenError> {
         let mut game = Game {
-            board: Vec::new(),
+            pieces: [[0; 6]; 2],
+            colors: [0; 2],
             state: GameState::InProgress,
-            turn: ColorState::White,
+            winner: None,
+            turn: Color::White,
             castling: vec![
                 Castling::WhiteKing,
                 Castling::WhiteQueen,
                 Castling::BlackKing,
                 Castling::BlackQueen,
             ],
-            promotion: 0b0000_0110,
-            enpassant: "-".to_string(),
+            enpassant: None,
+            halfmove: 0,
+            fullmove: 1,
+            hash: 0,
+            positions: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            start: None,
         };
-        game.apply_fen(fen);
-        game
+        game.apply_fen(fen)?;
+        let start = game.to_fen();
+        if start != STARTING_FEN {
+            game.start = Some(start);
+        }
+        game.hash = game.full_hash();
+        game.positions.push(game.repetition_key());
+        game.update_state();
+        Ok(game)
     }
 
     // Return current GameState
@@ -76,102 +304,214 @@ impl Game {
         self.state
     }
 
+    // Check if no more moves may be made
+    pub fn is_game_over(&self) -> bool {
+        !matches!(
+            self.state,
+            GameState::InProgress
+                | GameState::Check
+                | GameState::Draw(DrawReason::FiftyMoveRule)
+                | GameState::Draw(DrawReason::ThreefoldRepetition)
+        )
+    }
+
+    // Return the color of the player who won by checkmate
+    pub fn get_winner(&self) -> Option<Color> {
+        self.winner
+    }
+
+    // Return the color of the player in turn
+    pub fn get_turn(&self) -> Color {
+        self.turn
+    }
+
+    // Return the piece standing on a square
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.piece(square.index())
+    }
+
+    // Find the piece standing on a tile
+    fn piece(&self, index: usize) -> Option<Piece> {
+        let tile = bitboard::bit(index);
+        let color = if self.colors[0] & tile != 0 {
+            Color::White
+        } else if self.colors[1] & tile != 0 {
+            Color::Black
+        } else {
+            return None;
+        };
+        PieceKind::ALL
+            .iter()
+            .find(|kind| self.pieces[color.index()][kind.index()] & tile != 0)
+            .map(|kind| Piece::new(color, *kind))
+    }
+
+    // Put a piece on a tile, or empty it, replacing what stood there and keeping the Zobrist
+    // key up to date
+    fn set_piece(&mut self, index: usize, piece: Option<Piece>) {
+        let tile = bitboard::bit(index);
+        if let Some(old) = self.piece(index) {
+            self.colors[old.color.index()] &= !tile;
+            self.pieces[old.color.index()][old.kind.index()] &= !tile;
+            self.hash ^= zobrist::piece_key(old, index);
+        }
+        if let Some(piece) = piece {
+            self.colors[piece.color.index()] |= tile;
+            self.pieces[piece.color.index()][piece.kind.index()] |= tile;
+            self.hash ^= zobrist::piece_key(piece, index);
+        }
+    }
+
+    // Get the bitboard of one kind of piece of a color
+    fn bitboard(&self, color: Color, kind: PieceKind) -> Bitboard {
+        self.pieces[color.index()][kind.index()]
+    }
+
+    // Get the bitboard of all pieces on the board
+    fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
     // Take in fen string and apply the board state to the game
-    fn apply_fen(&mut self, fen: &str) {
+    fn apply_fen(&mut self, fen: &str) -> Result<(), FenError> {
         // Translate FEN string instruction
-        let fen_checker: Vec<String> = fen
-            .split(" ")
-            .map(|_check| _check.to_string())
-            .collect::<Vec<String>>();
+        let fen_checker: Vec<&str> = fen.split_whitespace().collect();
+        if fen_checker.len() != 6 {
+            return Err(FenError::WrongFieldCount(fen_checker.len()));
+        }
 
         // Get board positions
         // (fen_checker[0])
-        self.board = {
-            fen_checker[0]
-                .split("/")
-                .map(|_char| {
-                    let mut holder: Vec<u8> = Vec::new();
-                    for x in _char.chars() {
-                        match x {
-                            'K' => holder.push(0b0001_0001),
-                            'k' => holder.push(0b0000_1001),
-                            'Q' => holder.push(0b0001_0110),
-                            'q' => holder.push(0b0000_1110),
-                            'B' => holder.push(0b0001_0101),
-                            'b' => holder.push(0b0000_1101),
-                            'N' => holder.push(0b0001_0100),
-                            'n' => holder.push(0b0000_1100),
-                            'R' => holder.push(0b0001_0011),
-                            'r' => holder.push(0b0000_1011),
-                            'P' => holder.push(0b0001_0010),
-                            'p' => holder.push(0b0000_1010),
-                            _ => {
-                                for _ in 0..x.to_digit(10).unwrap() as usize {
-                                    holder.push(0b0000_0000);
-                                }
-                            }
-                        }
+        let ranks: Vec<&str> = fen_checker[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        let mut board: Vec<Option<Piece>> = Vec::new();
+        for (rank, tiles) in ranks.iter().enumerate() {
+            for x in tiles.chars() {
+                match (x, Piece::from_char(x)) {
+                    (_, Some(piece)) => board.push(Some(piece)),
+                    ('1'..='8', None) => {
+                        let empty = x.to_digit(10).unwrap_or(0) as usize;
+                        board.resize(board.len() + empty, None);
                     }
-                    return holder;
-                })
-                .flatten()
-                .collect::<Vec<u8>>()
-        };
+                    _ => return Err(FenError::InvalidPiece(x)),
+                }
+            }
+            // Ranks are counted from 8 down to 1 in FEN
+            if board.len() != (rank + 1) * 8 {
+                return Err(FenError::InvalidRankLength(8 - rank));
+            }
+        }
 
         // Get turn
         // (fen_checker[1])
-        match fen_checker[1].as_str() {
-            "w" => self.turn = ColorState::White,
-            "b" => self.turn = ColorState::Black,
-            _ => {}
-        }
+        let turn = match fen_checker[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::InvalidTurn(fen_checker[1].to_string())),
+        };
 
         // Get castling availability
         // (fen_checker[2])
-        
+        let castling_error = || FenError::InvalidCastling(fen_checker[2].to_string());
+        let mut castling: Vec<Castling> = Vec::new();
+        if fen_checker[2] != "-" {
+            for x in fen_checker[2].chars() {
+                let right = match x {
+                    'K' => Castling::WhiteKing,
+                    'Q' => Castling::WhiteQueen,
+                    'k' => Castling::BlackKing,
+                    'q' => Castling::BlackQueen,
+                    _ => return Err(castling_error()),
+                };
+                if castling.contains(&right) {
+                    return Err(castling_error());
+                }
+                castling.push(right);
+            }
+            if castling.is_empty() {
+                return Err(castling_error());
+            }
+        }
+        // Keep rights in the KQkq order used when writing FEN
+        castling.sort_by_key(|right| match right {
+            Castling::WhiteKing => 0,
+            Castling::WhiteQueen => 1,
+            Castling::BlackKing => 2,
+            Castling::BlackQueen => 3,
+        });
+
         // Get en passent availability
         // (fen_checker[3])
-        self.enpassant = fen_checker[3].to_string();
+        // The target square lies behind a pawn of the side that just moved
+        let enpassant_rank = match turn {
+            Color::White => 5,
+            Color::Black => 2,
+        };
+        let enpassant = match fen_checker[3] {
+            "-" => None,
+            square => match square.parse::<Square>() {
+                Ok(square) if square.rank() == enpassant_rank => Some(square),
+                _ => return Err(FenError::InvalidEnPassant(square.to_string())),
+            },
+        };
+        // The pawn stands in front of the target, and the tiles it passed over are empty
+        if let Some(square) = enpassant {
+            let (pawn, behind) = match turn {
+                Color::White => (square.index() + 8, square.index() - 8),
+                Color::Black => (square.index() - 8, square.index() + 8),
+            };
+            let pawn_moved = board[pawn] == Some(Piece::new(turn.opposite(), PieceKind::Pawn));
+            if !pawn_moved || board[square.index()].is_some() || board[behind].is_some() {
+                return Err(FenError::InvalidEnPassant(fen_checker[3].to_string()));
+            }
+        }
 
         // Halfmove clock
         // (fen_checker[4])
+        let halfmove: u32 = fen_checker[4]
+            .parse()
+            .map_err(|_| FenError::InvalidHalfmoveClock(fen_checker[4].to_string()))?;
 
         // Fullmove number
         // (fen_checker[5])
+        let fullmove: u32 = match fen_checker[5].parse() {
+            Ok(number) if number > 0 => number,
+            _ => return Err(FenError::InvalidFullmoveNumber(fen_checker[5].to_string())),
+        };
+
+        self.pieces = [[0; 6]; 2];
+        self.colors = [0; 2];
+        for (index, piece) in board.into_iter().enumerate() {
+            self.set_piece(index, piece);
+        }
+        self.turn = turn;
+        self.castling = castling;
+        self.enpassant = enpassant;
+        self.halfmove = halfmove;
+        self.fullmove = fullmove;
+        Ok(())
     }
 
     // Get current board as fen string
-    fn to_fen(&self) -> String {
+    pub fn to_fen(&self) -> String {
         let mut fen = String::from("");
         let mut position: u8 = 0;
         while position < 64 {
             let mut next_position: u8 = 1;
-            if position % 8 == 0 && position != 0 {
+            if position.is_multiple_of(8) && position != 0 {
                 fen.push('/');
             }
-            match &self.board[position as usize] {
-                0b0001_0001 => fen.push('K'),
-                0b0000_1001 => fen.push('k'),
-                0b0001_0110 => fen.push('Q'),
-                0b0000_1110 => fen.push('q'),
-                0b0001_0101 => fen.push('B'),
-                0b0000_1101 => fen.push('b'),
-                0b0001_0100 => fen.push('N'),
-                0b0000_1100 => fen.push('n'),
-                0b0001_0011 => fen.push('R'),
-                0b0000_1011 => fen.push('r'),
-                0b0001_0010 => fen.push('P'),
-                0b0000_1010 => fen.push('p'),
-                _ => {
+            match self.piece(position as usize) {
+                Some(piece) => fen.push(piece.to_char()),
+                None => {
                     let mut x: u8 = 0;
                     while (position % 8) + x < 8 && position + x < 64 {
-                        if self.board[(position + x) as usize] == 0b0000_0000 as u8 {
-                            x += 1;
-                        } else if position % 8 == 0 {
-                            break;
-                        } else {
+                        if self.piece((position + x) as usize).is_some() {
                             break;
                         }
+                        x += 1;
                     }
                     next_position = x;
                     fen.push(match x {
@@ -192,8 +532,8 @@ impl Game {
         fen.push(' ');
 
         match self.turn {
-            ColorState::White => fen.push('w'),
-            ColorState::Black => fen.push('b'),
+            Color::White => fen.push('w'),
+            Color::Black => fen.push('b'),
         }
         fen.push(' ');
 
@@ -203,665 +543,685 @@ impl Game {
                 Castling::WhiteQueen => fen.push('Q'),
                 Castling::BlackKing => fen.push('k'),
                 Castling::BlackQueen => fen.push('q'),
-                _ => fen.push('-'),
             }
         }
+        if self.castling.is_empty() {
+            fen.push('-');
+        }
         fen.push(' ');
 
+        match self.enpassant {
+            Some(square) => fen.push_str(&square.to_string()),
+            None => fen.push('-'),
+        }
+        fen.push(' ');
+
+        fen.push_str(&self.halfmove.to_string());
+        fen.push(' ');
+
+        fen.push_str(&self.fullmove.to_string());
+
         fen
     }
 
     // Function to move pieces
-    pub fn move_piece(&mut self, current_tile: &str, new_tile: &str) {
-        // Terrible method of determining what pieces arre allowed to be moved
-        // Hardcode for both colors how to move pieces because I have no clue what I am doing
-        match self.turn {
+    pub fn move_piece(&mut self, mv: Move) -> Result<(), ChessError> {
+        if self.is_game_over() {
+            return Err(ChessError::GameOver);
+        }
+        let current = mv.from.index();
 
-            // Only move white pieces if it is white's turn
-            ColorState::White => {
-                if &self.board[Game::grid_to_vector(&current_tile) as usize] >> 3 == 0b0000_0010 {
-                    let moves = match self.get_valid_moves(current_tile) {
-                        Some(vector) => vector,
-                        None => Vec::new(),
-                    };
-                    
-                    if moves.contains(&Game::grid_to_vector(&new_tile)) {
-                        if self.board[Game::grid_to_vector(&current_tile) as usize] << 5 == 0b0100_0000 && (Game::grid_to_vector(&new_tile) as isize - Game::grid_to_vector(&current_tile) as isize).abs() == 16 {
-                            self.enpassant = new_tile.to_string();
-                        }
-                        self.board[Game::grid_to_vector(&new_tile) as usize] =
-                            self.board[Game::grid_to_vector(&current_tile) as usize];
-                        self.board[Game::grid_to_vector(&current_tile) as usize] =
-                            0b0000_0000 as u8;
-
-                        if &self.board[Game::grid_to_vector(&new_tile) as usize] << 5 == 0b0100_0000 
-                            && Game::grid_to_vector(&new_tile) < 8 {
-                            self.board[Game::grid_to_vector(&new_tile) as usize] = self.promotion as u8 + 16;
-                        }
-                        // Make next turn black
-                        self.turn = ColorState::Black;
-                        self.state = GameState::InProgress;
-                    }
-                }
-            }
-            // Only move black pieces if it is white's turn
-            ColorState::Black => {
-                if &self.board[Game::grid_to_vector(&current_tile) as usize] >> 3 == 0b0000_0001 {
-                    let moves = match self.get_valid_moves(current_tile) {
-                        Some(vector) => vector,
-                        None => Vec::new(),
-                    };
-
-                    if moves.contains(&Game::grid_to_vector(&new_tile)) {
-                        if self.board[Game::grid_to_vector(&current_tile) as usize] << 5 == 0b0100_0000 && (Game::grid_to_vector(&new_tile) as isize - Game::grid_to_vector(&current_tile) as isize).abs() == 16 {
-                            self.enpassant = new_tile.to_string();
-                        }
-                        self.board[Game::grid_to_vector(&new_tile) as usize] =
-                            self.board[Game::grid_to_vector(&current_tile) as usize];
-                        self.board[Game::grid_to_vector(&current_tile) as usize] =
-                            0b0000_0000 as u8;
-
-                        if &self.board[Game::grid_to_vector(&new_tile) as usize] << 5 == 0b0100_0000 
-                            && Game::grid_to_vector(&new_tile) > 55 {
-                            self.board[Game::grid_to_vector(&new_tile) as usize] = self.promotion as u8 + 8;
-                        }   
-                        // Make next turn white
-                        self.turn = ColorState::White;
-                        self.state = GameState::InProgress;
-                    }
-                }
+        // Only move pieces of the color that is in turn
+        let piece = match self.piece(current) {
+            Some(piece) => piece,
+            None => return Err(ChessError::NoPieceOnSquare),
+        };
+        if piece.color != self.turn {
+            return Err(ChessError::NotYourTurn);
+        }
+
+        // Pawns reaching the last row become a queen unless another piece is chosen
+        let is_last_row = mv.to.rank() == 0 || mv.to.rank() == 7;
+        let mv = match mv.promotion {
+            Some(kind @ PieceKind::King) | Some(kind @ PieceKind::Pawn) => {
+                return Err(ChessError::InvalidPromotion(kind));
             }
+            None if piece.kind == PieceKind::Pawn && is_last_row => Move {
+                promotion: Some(PieceKind::Queen),
+                ..mv
+            },
+            _ => mv,
+        };
+
+        if !self.get_valid_moves(mv.from).contains(&mv) {
+            return Err(ChessError::IllegalMove);
         }
-        self.enpassant = "-".to_string();
+
+        // A new move replaces the moves that were taken back
+        self.undone.clear();
+        self.play(mv);
+        Ok(())
+    }
+
+    // Make a move that is known to be legal and add it to the history
+    fn play(&mut self, mv: Move) {
+        let undo = self.make_move(mv);
+        self.history.push(HistoryEntry {
+            mv,
+            captured: undo.captured,
+            castling: undo.castling,
+            enpassant: undo.enpassant,
+            halfmove: undo.halfmove,
+            fullmove: undo.fullmove,
+        });
+        self.update_state();
     }
-    
 
-    pub fn set_promotion(&mut self, piece: char) {
-        self.promotion = match piece {
-            'q' | 'Q' => 0b0000_0110,
-            'b' | 'B' => 0b0000_0101,
-            'n' | 'N' => 0b0000_0100,
-            'r' | 'R' => 0b0000_0011,
-            'p' | 'P' => 0b0000_0010,
-            _ => panic!(),
+    // Take back the last move, returning it
+    pub fn undo(&mut self) -> Option<Move> {
+        let entry = self.history.pop()?;
+        let undo = UndoInfo {
+            captured: entry.captured,
+            castling: entry.castling,
+            enpassant: entry.enpassant,
+            halfmove: entry.halfmove,
+            fullmove: entry.fullmove,
+            state: self.state,
+            winner: self.winner,
+            hash: self.hash,
         };
+        self.unmake_move(entry.mv, undo);
+        // The history does not keep the key from before the move, so it is worked out again
+        self.hash = self.full_hash();
+        self.undone.push(entry.mv);
+        self.update_state();
+        Some(entry.mv)
     }
 
-    // Get grid position into vector index
-    fn grid_to_vector(tile: &str) -> u8 {
-        (match Game::parse_to_index(tile) {
-            Some(x) => x,
-            None => 0,
-        }) as u8
-    }
-
-    // Function to get vector index as a grid position
-    fn vector_to_grid(input: u8) -> String {
-        let row = input / 8;
-        let column = input % 8;
-
-        // Get row as letter
-        let mut row_string: String = match row {
-            0 => "A",
-            1 => "B",
-            2 => "C",
-            3 => "D",
-            4 => "E",
-            5 => "F",
-            6 => "G",
-            7 => "H",
-            _ => "A",
-        }
-        .to_owned();
-
-        // Get column as number
-        let col_string: &str = match column {
-            0 => "1",
-            1 => "2",
-            2 => "3",
-            3 => "4",
-            4 => "5",
-            5 => "6",
-            6 => "7",
-            7 => "8",
-            _ => "1",
+    // Make a move without checking that it is legal or updating the state of the game, for
+    // searching through positions. Moves must come from the generated moves, so promotions
+    // always name their piece. Returns what unmake_move needs to take the move back
+    pub fn make_move(&mut self, mv: Move) -> UndoInfo {
+        let current = mv.from.index();
+        let new = mv.to.index();
+        let mut undo = UndoInfo {
+            captured: self.piece(new),
+            castling: self.castling.clone(),
+            enpassant: self.enpassant,
+            halfmove: self.halfmove,
+            fullmove: self.fullmove,
+            state: self.state,
+            winner: self.winner,
+            hash: self.hash,
         };
+        let piece = match self.piece(current) {
+            Some(piece) => piece,
+            None => return undo,
+        };
+        // The keys of the castling rights and en passant file are put back after the move
+        self.hash ^= self.castling_hash() ^ self.enpassant_hash();
+
+        // Pawn moves and captures reset the halfmove clock
+        let is_pawn = piece.kind == PieceKind::Pawn;
+        let resets_clock = is_pawn || self.piece(new).is_some();
+        self.set_piece(new, Some(piece));
+        self.set_piece(current, None);
+
+        // The en passant target only lasts for one move
+        let enpassant = self.enpassant.take();
+        if is_pawn && enpassant == Some(mv.to) {
+            // The captured pawn stands beside the capturing pawn, not on the target tile
+            let victim = Game::enpassant_victim(current, new);
+            undo.captured = self.piece(victim);
+            self.set_piece(victim, None);
+        }
+        // Pawns moving two tiles may be captured on the tile they passed
+        if is_pawn && (new as isize - current as isize).abs() == 16 {
+            self.enpassant = Some(Square(((current + new) / 2) as u8));
+        }
+
+        // Castling moves the king two tiles and brings the rook along
+        if piece.kind == PieceKind::King {
+            if let Some(right) = Game::castling_move(&self.castling, current, new) {
+                self.set_piece(right.rook_target(), self.piece(right.rook_tile()));
+                self.set_piece(right.rook_tile(), None);
+            }
+        }
+        // Moving the king or a rook, or having a rook captured, loses the right to castle
+        self.castling.retain(|right| {
+            current != right.king_tile() && current != right.rook_tile() && new != right.rook_tile()
+        });
 
-        // Add column to row string
-        row_string.push_str(&col_string);
-        row_string // Return position
+        if let Some(kind) = mv.promotion {
+            self.set_piece(new, Some(Piece::new(self.turn, kind)));
+        }
+
+        if self.turn == Color::Black {
+            self.fullmove += 1;
+        }
+        // Make next turn the other color
+        self.turn = self.turn.opposite();
+        self.halfmove = if resets_clock { 0 } else { self.halfmove + 1 };
+        self.hash ^= self.castling_hash() ^ self.enpassant_hash() ^ zobrist::turn_key();
+        self.positions.push(self.repetition_key());
+        undo
     }
 
-    // Get all valid moves possible by each piece
-    fn get_valid_moves(&self, tile: &str) -> Option<Vec<u8>> {
-        let position = match Game::parse_to_index(tile) {
-            Some(x) => x,
-            None => 0,
+    // Take back a move made by make_move, restoring the game exactly as it was before
+    pub fn unmake_move(&mut self, mv: Move, undo: UndoInfo) {
+        let current = mv.from.index();
+        let new = mv.to.index();
+        let piece = match self.piece(new) {
+            Some(piece) => piece,
+            None => return,
         };
 
-        // Only allow king piece to move if game in check state
-        let selected_piece: u8 = match &self.state {
-            GameState::Check => match &self.turn {
-                ColorState::White => 0b0001_0001,
-                ColorState::Black => 0b0000_1001,
-            },
-            _ => self.board[position],
+        // A promoted piece goes back to being a pawn
+        let moved = match mv.promotion {
+            Some(_) => Piece::new(piece.color, PieceKind::Pawn),
+            None => piece,
         };
-        
-        // Return possible moves
-        return match selected_piece {
-            // King piece
-            0b0001_0001 | 0b0000_1001 => {
-                let mut index = 0;
-                let mut threatned_tiles: Vec<Vec<u8>> = Vec::new();
-                for piece in &self.board {
-                    // Get opposite color king piece possible moves
-                    if piece >> 3 ^ selected_piece >> 3 != 0b0000_0000 && piece << 5 == 0b0010_0000
-                    {
-                        threatned_tiles.push(self.get_surrounding_tiles(
-                            (index as u8).into(),
-                            true,
-                            true,
-                            false,
-                        ));
-                    }
-                    // Get all opposite color pieces possible moves
-                    else if piece >> 3 ^ selected_piece >> 3 != 0b0000_0000 {
-                        match self.get_valid_moves(&Game::vector_to_grid(index)) {
-                            Some(vector) => threatned_tiles.push(vector),
-                            None => {}
-                        }
-                    }
-                    index += 1;
-                }
-                let threatned_moves: Vec<u8> = threatned_tiles.into_iter().flatten().collect();
-                let mut allowed_moves: Vec<u8> = Vec::new();
+        self.set_piece(current, Some(moved));
+        self.set_piece(new, undo.captured);
 
-                // Only allow piece to move into non-threatned tiles
-                for tile in self.get_surrounding_tiles(position, true, true, false) {
-                    if !threatned_moves.contains(&tile) {
-                        allowed_moves.push(tile);
-                    }
-                }
-                Some(allowed_moves)
+        // The pawn captured en passant goes back beside the capturing pawn
+        if moved.kind == PieceKind::Pawn && undo.enpassant == Some(mv.to) {
+            self.set_piece(new, None);
+            self.set_piece(Game::enpassant_victim(current, new), undo.captured);
+        }
+
+        // The rook goes back to its corner after castling
+        if moved.kind == PieceKind::King {
+            if let Some(right) = Game::castling_move(&undo.castling, current, new) {
+                self.set_piece(right.rook_tile(), self.piece(right.rook_target()));
+                self.set_piece(right.rook_target(), None);
             }
-            // Queen piece
-            0b0001_0110 | 0b0000_1110 => {
-                Some(self.get_surrounding_tiles(position, true, true, true))
+        }
+
+        self.castling = undo.castling;
+        self.enpassant = undo.enpassant;
+        self.halfmove = undo.halfmove;
+        self.fullmove = undo.fullmove;
+        self.state = undo.state;
+        self.winner = undo.winner;
+        self.hash = undo.hash;
+        self.turn = self.turn.opposite();
+        self.positions.pop();
+    }
+
+    // Check if a move is a king castling, the only move of a king over two files
+    fn is_castling(&self, mv: Move) -> bool {
+        self.piece_at(mv.from).map(|piece| piece.kind) == Some(PieceKind::King)
+            && (mv.from.file() as i8 - mv.to.file() as i8).abs() == 2
+    }
+
+    // Find the castling right a king move uses, if it is a castling move
+    fn castling_move(castling: &[Castling], current: usize, new: usize) -> Option<Castling> {
+        castling
+            .iter()
+            .find(|right| right.king_tile() == current && right.king_target() == new)
+            .copied()
+    }
+
+    // Play the last move that was taken back again, returning it
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.undone.pop()?;
+        self.play(mv);
+        Some(mv)
+    }
+
+    // Return the moves played so far, oldest first
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    // Find out if the player in turn is in check and if they have any moves left
+    fn update_state(&mut self) {
+        let in_check = self.is_in_check(self.turn);
+        let has_moves = bitboard::tiles(self.colors[self.turn.index()])
+            .any(|index| !self.get_valid_moves(Square(index as u8)).is_empty());
+        let repetitions = self.count_repetitions();
+        self.winner = None;
+        self.state = match (in_check, has_moves) {
+            (true, false) => {
+                self.winner = Some(self.turn.opposite());
+                GameState::Checkmate
             }
-            // Bishop piece
-            0b0001_0101 | 0b0000_1101 => {
-                Some(self.get_surrounding_tiles(position, true, false, true))
+            (false, false) => GameState::Stalemate,
+            // A checkmate on the last move counts over the move rules
+            _ if self.halfmove >= 150 => GameState::Draw(DrawReason::SeventyFiveMoveRule),
+            _ if repetitions >= 5 => GameState::Draw(DrawReason::FivefoldRepetition),
+            _ if self.is_insufficient_material() => {
+                GameState::Draw(DrawReason::InsufficientMaterial)
             }
-            // Knight piece
-            0b0001_0100 | 0b0000_1100 => Some(self.get_knight_moves(position)),
-            // Rook piece
-            0b0001_0011 | 0b0000_1011 => {
-                Some(self.get_surrounding_tiles(position, false, true, true))
+            // Play goes on after draws that may only be claimed, so a check matters more
+            (true, true) => GameState::Check,
+            _ if self.halfmove >= 100 => GameState::Draw(DrawReason::FiftyMoveRule),
+            _ if repetitions >= 3 => GameState::Draw(DrawReason::ThreefoldRepetition),
+            (false, true) => GameState::InProgress,
+        };
+    }
+
+    // Check if the player in turn may claim a draw by the fifty-move rule or threefold
+    // repetition, which the state does not show while in check
+    pub fn can_claim_draw(&self) -> bool {
+        !self.is_game_over() && (self.halfmove >= 100 || self.count_repetitions() >= 3)
+    }
+
+    // Get the Zobrist key of the position. It matches the keys of Polyglot opening books, and
+    // is the same on every run and platform
+    pub fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+
+    // Work out the Zobrist key from the whole position instead of move by move
+    fn full_hash(&self) -> u64 {
+        let mut hash = self.castling_hash() ^ self.enpassant_hash();
+        for index in 0..64 {
+            if let Some(piece) = self.piece(index) {
+                hash ^= zobrist::piece_key(piece, index);
             }
-            // Pawn piece
-            0b0001_0010 | 0b0000_1010 => Some(self.get_pawn_moves(position)),
-            _ => None,
+        }
+        if self.turn == Color::White {
+            hash ^= zobrist::turn_key();
+        }
+        hash
+    }
+
+    fn castling_hash(&self) -> u64 {
+        self.castling
+            .iter()
+            .fold(0, |hash, right| hash ^ zobrist::castling_key(*right))
+    }
+
+    // The en passant file counts when a pawn of the player in turn stands beside the pawn that
+    // moved two tiles, even if capturing it would leave the king in check. This is how
+    // Polyglot books key positions
+    fn enpassant_hash(&self) -> u64 {
+        match self.enpassant {
+            Some(target)
+                if PAWN_ATTACKS[self.turn.opposite().index()][target.index()]
+                    & self.bitboard(self.turn, PieceKind::Pawn)
+                    != 0 =>
+            {
+                zobrist::enpassant_key(target.file())
+            }
+            _ => 0,
+        }
+    }
+
+    // Get the key positions are compared by to find repetitions. Positions are only the same
+    // when the same moves can be made, so the en passant file counts only when the capture is
+    // legal
+    fn repetition_key(&self) -> u64 {
+        let can_capture = self.enpassant.is_some_and(|target| {
+            bitboard::tiles(self.bitboard(self.turn, PieceKind::Pawn)).any(|index| {
+                self.get_pawn_moves(index) & bitboard::bit(target.index()) != 0
+                    && self.is_safe_move(index, target.index())
+            })
+        });
+        if can_capture {
+            self.hash
+        } else {
+            self.hash ^ self.enpassant_hash()
+        }
+    }
+
+    // Count how often the current position has occurred since the last capture or pawn move
+    fn count_repetitions(&self) -> usize {
+        let current = match self.positions.last() {
+            Some(hash) => *hash,
+            None => return 0,
         };
+        self.positions
+            .iter()
+            .rev()
+            .take(self.halfmove as usize + 1)
+            .filter(|hash| **hash == current)
+            .count()
     }
 
-    // Get surrounding valid tiles
-    fn get_surrounding_tiles(
-        &self,
-        index: usize,
-        is_diagonal: bool,
-        is_linear: bool,
-        is_continous: bool,
-    ) -> Vec<u8> {
-        let mut tile_check = if is_linear { 0 } else { 1 };
-        let mut available_tiles: Vec<u8> = Vec::new();
-
-        // Empty tile will not have piece on top
-        if self.board[index] == 0b0000_0000 {
-            return available_tiles;
-        }
-        // Check all 8 surrounding tiles if piece can move 8 directions
-        // Otherwise check 4 surrounding tiles
-        while tile_check < 8 {
-            match tile_check {
-                // NORTH
-                0 => {
-                    for continous_loop in 1..8 {
-                        if index as isize - (8 * continous_loop as isize) >= 0 {
-                            if &self.board[index - (8 * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                            {
-                                available_tiles
-                                    .push((index - ((8 * continous_loop) as usize)) as u8);
-                                break;
-                            } else if &self.board[index - (8 * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                            {
-                                available_tiles
-                                    .push((index - ((8 * continous_loop) as usize)) as u8);
-                            } else {
-                                break;
-                            }
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // NORTH EAST
-                1 => {
-                    for continous_loop in 1..8 {
-                        if index as isize - (8 * continous_loop as isize) >= 0 {
-                            if &self.board[index - (8 + 1) * continous_loop] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                                && ((index % 8) + 1 * continous_loop) < 8
-                            {
-                                available_tiles
-                                    .push((index - ((8 + 1) * continous_loop) as usize) as u8);
-                                break;
-                            } else if &self.board[index - (8 + 1) * continous_loop] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                                && ((index % 8) + 1 * continous_loop) < 8
-                            {
-                                available_tiles
-                                    .push((index - ((8 + 1) * continous_loop) as usize) as u8);
-                            } else {
-                                break;
-                            }
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // EAST
-                2 => {
-                    for continous_loop in 1..8 {
-                        if index + (1 * continous_loop) < 64
-                            && &self.board[index + (1 * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                            && ((index % 8) + 1 * continous_loop) < 8
-                        {
-                            available_tiles.push((index + (1 * continous_loop) as usize) as u8);
-                            break;
-                        } else if index + (1 * continous_loop) < 64
-                            && &self.board[index + (1 * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                            && ((index % 8) + 1 * continous_loop) < 8
-                        {
-                            available_tiles.push((index + (1 * continous_loop) as usize) as u8);
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // SOUTH EAST
-                3 => {
-                    for continous_loop in 1..8 {
-                        if index + ((8 + 1) * continous_loop) < 64
-                            && &self.board[index + (8 + 1) * continous_loop] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                            && ((index % 8) + 1 * continous_loop) < 8
-                        {
-                            available_tiles
-                                .push((index + ((8 + 1) * continous_loop) as usize) as u8);
-                            break;
-                        } else if index + ((8 + 1) * continous_loop) < 64
-                            && &self.board[index + (8 + 1) * continous_loop] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                            && ((index % 8) + 1 * continous_loop) < 8
-                        {
-                            available_tiles
-                                .push((index + ((8 + 1) * continous_loop) as usize) as u8);
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // SOUTH
-                4 => {
-                    for continous_loop in 1..8 {
-                        if index + (8 * continous_loop) < 64
-                            && &self.board[index + (8 * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                        {
-                            available_tiles.push((index + (8 * continous_loop) as usize) as u8);
-                            break;
-                        } else if index + (8 * continous_loop) < 64
-                            && &self.board[index + (8 * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                        {
-                            available_tiles.push((index + (8 * continous_loop) as usize) as u8);
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // SOUTH WEST
-                5 => {
-                    for continous_loop in 1..8 {
-                        if index + (8 - 1) * continous_loop < 64
-                            && &self.board[index + ((8 - 1) * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                            && (index % 8) as isize - continous_loop as isize >= 0
-                        {
-                            available_tiles.push((index + (8 - 1) * continous_loop) as u8);
-                            break;
-                        } else if index + (8 - 1) * continous_loop < 64
-                            && &self.board[index + ((8 - 1) * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                            && (index % 8) as isize - continous_loop as isize >= 0
-                        {
-                            available_tiles.push((index + (8 - 1) * continous_loop) as u8);
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // WEST
-                6 => {
-                    for continous_loop in 1..8 {
-                        if index as isize - (1 * continous_loop as isize) >= 0 {
-                            if &self.board[index - (1 * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                                && (index % 8) as isize - continous_loop as isize >= 0
-                            {
-                                available_tiles.push((index - 1 * continous_loop) as u8);
-                                break;
-                            } else if &self.board[index - (1 * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                                && (index % 8) as isize - continous_loop as isize >= 0
-                            {
-                                available_tiles.push((index - 1 * continous_loop) as u8);
-                            } else {
-                                break;
-                            }
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                // NORTH WEST
-                7 => {
-                    for continous_loop in 1..8 {
-                        if index as isize - (8 + 1) as isize * continous_loop as isize >= 0
-                            && &self.board[index - ((8 + 1) * continous_loop)] >> 3
-                                | &self.board[index] >> 3
-                                == 0b0000_0011
-                            && (index % 8) as isize - continous_loop as isize >= 0
-                        {
-                            available_tiles.push((index - (8 + 1) * continous_loop) as u8);
-                            break;
-                        } else if index as isize - (8 + 1) as isize * continous_loop as isize >= 0
-                            && &self.board[index - ((8 + 1) * continous_loop)] >> 3
-                                ^ &self.board[index] >> 3
-                                != 0b0000_0000
-                            && (index % 8) as isize - continous_loop as isize >= 0
-                        {
-                            available_tiles.push((index - (8 + 1) * continous_loop) as u8);
-                        } else {
-                            break;
-                        }
-                        if !is_continous {
-                            break;
-                        }
-                    }
-                }
-                _ => {}
+    // Check if neither player can checkmate, which is when only kings and at most one knight
+    // or any bishops standing on tiles of the same color are left
+    fn is_insufficient_material(&self) -> bool {
+        let kinds = |kind: PieceKind| {
+            self.bitboard(Color::White, kind) | self.bitboard(Color::Black, kind)
+        };
+        if kinds(PieceKind::Pawn) | kinds(PieceKind::Rook) | kinds(PieceKind::Queen) != 0 {
+            return false;
+        }
+        let bishops = kinds(PieceKind::Bishop);
+        let minor_pieces = kinds(PieceKind::Knight) | bishops;
+        minor_pieces.count_ones() <= 1
+            || minor_pieces == bishops
+                && (bishops & LIGHT_TILES == 0 || bishops & !LIGHT_TILES == 0)
+    }
+
+    // Get all valid moves possible by each piece
+    fn get_valid_moves(&self, square: Square) -> Vec<Move> {
+        let position = square.index();
+
+        let selected_piece = match self.piece(position) {
+            Some(piece) => piece,
+            None => return Vec::new(),
+        };
+
+        // Get the moves of the piece as if its king could not be attacked. Pieces may move
+        // to any tile they attack that does not hold a piece of their own color
+        let own = self.colors[selected_piece.color.index()];
+        let occupied = self.occupied();
+        let moves = match selected_piece.kind {
+            PieceKind::King => KING_ATTACKS[position] & !own | self.get_castling_moves(position),
+            PieceKind::Queen => {
+                (bitboard::rook_attacks(position, occupied)
+                    | bitboard::bishop_attacks(position, occupied))
+                    & !own
             }
+            PieceKind::Bishop => bitboard::bishop_attacks(position, occupied) & !own,
+            PieceKind::Knight => KNIGHT_ATTACKS[position] & !own,
+            PieceKind::Rook => bitboard::rook_attacks(position, occupied) & !own,
+            PieceKind::Pawn => self.get_pawn_moves(position),
+        };
 
-            if is_diagonal {
-                tile_check += 1;
+        // Only allow moves that do not leave the own king attacked, which while in check
+        // means moving the king, capturing the checking piece or blocking its line
+        let mut valid_moves: Vec<Move> = Vec::new();
+        for tile in bitboard::tiles(moves) {
+            if !self.is_safe_move(position, tile) {
+                continue;
+            }
+            let target = Square(tile as u8);
+            // Every piece a pawn may promote to is a move of its own
+            if selected_piece.kind == PieceKind::Pawn && (target.rank() == 0 || target.rank() == 7)
+            {
+                for kind in PROMOTION_KINDS.iter() {
+                    valid_moves.push(Move::new_promotion(square, target, *kind));
+                }
             } else {
-                tile_check += 2;
+                valid_moves.push(Move::new(square, target));
             }
         }
-        available_tiles
+        valid_moves
     }
 
-    // Get knight moves
-    fn get_knight_moves(&self, index: usize) -> Vec<u8> {
-        let mut tile_check = 0;
-        let mut available_moves: Vec<u8> = Vec::new();
-        while tile_check < 8 {
-            match tile_check {
-                // Up, Left
-                0 => {
-                    if index as isize - 16 - 1 >= 0
-                        && &self.board[index - 16 - 1] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) - 1 >= 0
-                    {
-                        available_moves.push(index as u8 - 16 - 1);
-                    }
-                }
-                // Up, Right
-                1 => {
-                    if index as isize - 16 + 1 >= 0
-                        && &self.board[index - 16 + 1] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) + 1 < 8
-                    {
-                        available_moves.push(index as u8 - 16 - 1);
-                    }
-                }
-                // Right, Up
-                2 => {
-                    if index as isize - 8 + 2 >= 0
-                        && &self.board[index - 8 + 2] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) + 2 < 8
-                    {
-                        available_moves.push(index as u8 - 8 + 2);
-                    }
-                }
-                // Right, Down
-                3 => {
-                    if index as isize - 8 - 2 >= 0
-                        && &self.board[index - 8 - 2] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) - 2 < 8
-                    {
-                        available_moves.push(index as u8 - 8 - 2);
-                    }
-                }
-                // Down, Left
-                4 => {
-                    if index + 16 - 1 < 64
-                        && &self.board[index + 16 - 1] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) - 1 >= 0
-                    {
-                        available_moves.push(index as u8 + 16 - 1);
-                    }
-                }
-                // Down, Right
-                5 => {
-                    if index + 16 + 1 < 64
-                        && &self.board[index + 16 + 1] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) + 1 < 8
-                    {
-                        available_moves.push(index as u8 + 16 + 1);
-                    }
-                }
-                // Left, Up
-                6 => {
-                    if index - 8 - 2 >= 0
-                        && &self.board[index - 8 - 2] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) - 2 >= 0
-                    {
-                        available_moves.push(index as u8 - 8 - 2);
-                    }
-                }
-                // Left, Down
-                7 => {
-                    if index + 8 - 2 < 64
-                        && &self.board[index + 8 - 2] >> 3 ^ &self.board[index] >> 3 != 0b0000_0000
-                        && (index % 8) - 2 >= 0
-                    {
-                        available_moves.push(index as u8 + 8 - 2);
-                    }
+    // Check if the king of the moving piece is safe after the move has been made
+    fn is_safe_move(&self, current: usize, new: usize) -> bool {
+        let piece = match self.piece(current) {
+            Some(piece) => piece,
+            None => return false,
+        };
+        // Only the pieces are needed to look for attacks on the king
+        let mut after = Game {
+            castling: Vec::new(),
+            positions: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            start: None,
+            ..*self
+        };
+        if self.is_enpassant_capture(current, new) {
+            after.set_piece(Game::enpassant_victim(current, new), None);
+        }
+        after.set_piece(new, Some(piece));
+        after.set_piece(current, None);
+        !after.is_in_check(piece.color)
+    }
+
+    // Check if a move is a pawn capturing en passant
+    fn is_enpassant_capture(&self, current: usize, new: usize) -> bool {
+        self.enpassant.map(|square| square.index()) == Some(new)
+            && self.piece(current).map(|piece| piece.kind) == Some(PieceKind::Pawn)
+    }
+
+    // Get the tile of the pawn captured en passant, which is on the row the capture starts
+    // from and the column it ends on
+    fn enpassant_victim(current: usize, new: usize) -> usize {
+        current / 8 * 8 + new % 8
+    }
+
+    // Check if the king of a color is attacked, boards without that king are never in check
+    fn is_in_check(&self, color: Color) -> bool {
+        match bitboard::tiles(self.bitboard(color, PieceKind::King)).next() {
+            Some(index) => self.is_attacked(index, color.opposite()),
+            None => false,
+        }
+    }
+
+    // Check if any piece of a color attacks a tile
+    fn is_attacked(&self, index: usize, by: Color) -> bool {
+        self.attackers(index, by) != 0
+    }
+
+    // Get the pieces of a color attacking a tile. A tile is attacked by a piece when that
+    // piece would attack the piece's own tile if it stood on the attacked tile
+    fn attackers(&self, index: usize, by: Color) -> Bitboard {
+        let occupied = self.occupied();
+        let queens = self.bitboard(by, PieceKind::Queen);
+        PAWN_ATTACKS[by.opposite().index()][index] & self.bitboard(by, PieceKind::Pawn)
+            | KNIGHT_ATTACKS[index] & self.bitboard(by, PieceKind::Knight)
+            | KING_ATTACKS[index] & self.bitboard(by, PieceKind::King)
+            | bitboard::rook_attacks(index, occupied)
+                & (self.bitboard(by, PieceKind::Rook) | queens)
+            | bitboard::bishop_attacks(index, occupied)
+                & (self.bitboard(by, PieceKind::Bishop) | queens)
+    }
+
+    // Get the squares of the pieces of a color attacking a square
+    pub fn attackers_of(&self, square: Square, by: Color) -> Vec<Square> {
+        bitboard::tiles(self.attackers(square.index(), by))
+            .map(|index| Square(index as u8))
+            .collect()
+    }
+
+    // Check if any piece of a color attacks a square, whether or not it could move there
+    pub fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        self.is_attacked(square.index(), by)
+    }
+
+    // Get the squares of the pieces giving check to the player in turn
+    pub fn checkers(&self) -> Vec<Square> {
+        match bitboard::tiles(self.bitboard(self.turn, PieceKind::King)).next() {
+            Some(king) => self.attackers_of(Square(king as u8), self.turn.opposite()),
+            None => Vec::new(),
+        }
+    }
+
+    // Get the squares of the pieces of a color that can not leave the line between their king
+    // and a sliding piece of the other color without exposing the king
+    pub fn pinned_pieces(&self, color: Color) -> Vec<Square> {
+        let king = match bitboard::tiles(self.bitboard(color, PieceKind::King)).next() {
+            Some(king) => king,
+            None => return Vec::new(),
+        };
+        let other = color.opposite();
+        let queens = self.bitboard(other, PieceKind::Queen);
+        // Sliders that would attack the king if no pieces of this color were in the way
+        let enemies = self.colors[other.index()];
+        let snipers = bitboard::rook_attacks(king, enemies)
+            & (self.bitboard(other, PieceKind::Rook) | queens)
+            | bitboard::bishop_attacks(king, enemies)
+                & (self.bitboard(other, PieceKind::Bishop) | queens);
+        bitboard::tiles(snipers)
+            .filter_map(|sniper| {
+                let blockers = bitboard::between(king, sniper) & self.occupied();
+                if blockers.count_ones() == 1 && blockers & self.colors[color.index()] != 0 {
+                    Some(Square(blockers.trailing_zeros() as u8))
+                } else {
+                    None
                 }
-                // (?)
-                _ => {}
-            }
+            })
+            .collect()
+    }
 
-            tile_check += 1;
+    // Get the tiles a king can castle to
+    fn get_castling_moves(&self, index: usize) -> Bitboard {
+        let mut available_moves: Bitboard = 0;
+        let color = match self.piece(index) {
+            Some(piece) => piece.color,
+            None => return available_moves,
+        };
+        for right in self.castling.iter().filter(|right| right.color() == color) {
+            if right.king_tile() != index
+                || self.piece(right.rook_tile()) != Some(Piece::new(color, PieceKind::Rook))
+            {
+                continue;
+            }
+            // Every tile between the king and the rook must be empty
+            let (low, high) = if right.rook_tile() < index {
+                (right.rook_tile(), index)
+            } else {
+                (index, right.rook_tile())
+            };
+            if (low + 1..high).any(|tile| self.occupied() & bitboard::bit(tile) != 0) {
+                continue;
+            }
+            // The king may not castle out of, through or into check
+            let path = [index, right.rook_target(), right.king_target()];
+            if path.iter().any(|tile| self.is_attacked(*tile, color.opposite())) {
+                continue;
+            }
+            available_moves |= bitboard::bit(right.king_target());
         }
         available_moves
     }
 
     // Get possible pawn moves
-    fn get_pawn_moves(&self, index: usize) -> Vec<u8> {
-        let mut available_moves: Vec<u8> = Vec::new();
-        match self.board[index] {
-            // For black pieces
-            0b0000_1010 => {
-                if index + 8 < 64 && self.board[index + 8] == 0b0000_0000  {
-                    available_moves.push(index as u8 + 8);
-                    if index >= 8 && index < 16 && self.board[index + 16] == 0b0000_0000 {
-                        available_moves.push(index as u8 + 16);
+    fn get_pawn_moves(&self, index: usize) -> Bitboard {
+        let color = match self.piece(index) {
+            Some(piece) => piece.color,
+            None => return 0,
+        };
+        // White pawns move up the board and black pawns down
+        let (one_step, start_row) = match color {
+            Color::White => (index.checked_sub(8), 6),
+            Color::Black => (Some(index + 8).filter(|tile| *tile < 64), 1),
+        };
+        let mut available_moves: Bitboard = 0;
+        if let Some(one_step) = one_step {
+            if self.piece(one_step).is_none() {
+                available_moves |= bitboard::bit(one_step);
+                if index / 8 == start_row {
+                    let two_steps = 2 * one_step - index;
+                    if self.piece(two_steps).is_none() {
+                        available_moves |= bitboard::bit(two_steps);
                     }
                 }
-                if self.board[index + 8 - 1] >> 3 | self.board[index] >> 3 == 0b0000_0011 && (index as isize - 1) % 8 >= 0 {
-                    available_moves.push(index as u8 + 8 - 1);
-                }
-                if self.board[index + 8 + 1] >> 3 | self.board[index] >> 3 == 0b0000_0011 && (index as isize - 1) % 8 < 8 {
-                    available_moves.push(index as u8 + 8 + 1);
-                }
+            }
+        }
 
-                // Check for enpassant
-                /* if self.enpassant != "-".to_string() {
-                    if Game::vector_to_grid(index as u8 + 1) == self.enpassant && 1 + index % 8 < 8 {
-                        available_moves.push(index as u8 + 8 + 1);
-                    } else if Game::vector_to_grid(index as u8 - 1) == self.enpassant && (index - 1) & 8 >= 0 {
-                        available_moves.push(index as u8 + 8 - 1);
-                    } 
-                } */
-            }
-            // For white pieces
-            0b0001_0010 => {
-                if index as isize - 8 >= 0 && self.board[index - 8] == 0b0000_0000  {
-                    available_moves.push(index as u8 - 8);
-                    if index < 56 && index >= 48 && self.board[index - 16] == 0b0000_0000 {
-                        available_moves.push(index as u8 - 16);
-                    }
-                }
-                if index as isize - 8 - 1 > 0 && self.board[index - 8 - 1] >> 3 | self.board[index] >> 3 == 0b0000_0011 && (index as isize - 1) % 8 >= 0 {
-                    available_moves.push(index as u8 - 8 - 1);
-                }
-                if index as isize - 8 + 1 > 0 && self.board[index - 8 + 1] >> 3 | self.board[index] >> 3 == 0b0000_0011 && (index as isize - 1) % 8 < 8 {
-                    available_moves.push(index as u8 - 8 + 1);
-                }
+        // Pawns capture diagonally forward, or onto the en passant target
+        let mut targets = self.colors[color.opposite().index()];
+        if let Some(square) = self.enpassant {
+            targets |= bitboard::bit(square.index());
+        }
+        available_moves | PAWN_ATTACKS[color.index()][index] & targets
+    }
 
-                // Check for enpassant
-                /* if self.enpassant != "-".to_string() {
-                    if Game::vector_to_grid(index as u8 + 1) == self.enpassant && 1 + index % 8 < 8 {
-                        available_moves.push(index as u8 - 8 + 1);
-                    } else if Game::vector_to_grid(index as u8 - 1) == self.enpassant && (index - 1) & 8 >= 0 {
-                        available_moves.push(index as u8 - 8 - 1);
-                    }    
-                } */
-            }
-            _ => { }
+    // Get the legal moves of the piece on a square, with their flags filled in. Pieces of the
+    // player not in turn have none
+    pub fn legal_moves_from(&self, square: Square) -> Vec<Move> {
+        if self.is_game_over() || self.piece_at(square).map(|piece| piece.color) != Some(self.turn)
+        {
+            return Vec::new();
         }
-        available_moves
+        self.add_flags(self.get_valid_moves(square))
     }
 
-    // Function to remove a piece from a tile
-    // Might be the most useless function in the entire program
-    fn remove_piece(&mut self, tile: &str) {
-        self.board[Game::grid_to_vector(tile) as usize] = 0b0000_0000;
+    // Get the legal moves of every piece of the player in turn, with their flags filled in
+    pub fn all_legal_moves(&self) -> Vec<Move> {
+        if self.is_game_over() {
+            return Vec::new();
+        }
+        self.add_flags(self.get_all_valid_moves())
     }
 
-    // Parse grid position to vector index
-    // * You may notice another method calls on this method and has the same purpose... I don't
-    // know why, but it just is like this
-    fn parse_to_index(position: &str) -> Option<usize> {
-        let mut chars = position.chars();
-        let row = match chars.next() {
-            // Much nicer looking than my other match functions
-            Some(c @ 'A'..='H') => c as usize - 'A' as usize,
-            _ => return None,
-        };
-        let column = match chars.next() {
-            Some(c @ '1'..='8') => c as usize - '1' as usize,
-            _ => return None,
-        };
-        Some(row * 8 + column)
+    // Check if a move can be played now. Promotions must name their piece
+    pub fn is_legal(&self, mv: Move) -> bool {
+        !self.is_game_over()
+            && self.piece_at(mv.from).map(|piece| piece.color) == Some(self.turn)
+            && self.get_valid_moves(mv.from).contains(&mv)
+    }
+
+    // Fill in what each move does, playing it to see if it gives check
+    fn add_flags(&self, moves: Vec<Move>) -> Vec<Move> {
+        let mut after = self.clone();
+        moves
+            .into_iter()
+            .map(|mut mv| {
+                let kind = self.piece_at(mv.from).map(|piece| piece.kind);
+                let is_pawn = kind == Some(PieceKind::Pawn);
+                mv.flags.en_passant = is_pawn && self.enpassant == Some(mv.to);
+                mv.flags.capture = self.piece_at(mv.to).is_some() || mv.flags.en_passant;
+                mv.flags.castle = self.is_castling(mv);
+                mv.flags.promotion = mv.promotion.is_some();
+                let undo = after.make_move(mv);
+                mv.flags.check = after.is_in_check(after.turn);
+                after.unmake_move(mv, undo);
+                mv
+            })
+            .collect()
+    }
+
+    // Get the valid moves of every piece of the player in turn
+    fn get_all_valid_moves(&self) -> Vec<Move> {
+        bitboard::tiles(self.colors[self.turn.index()])
+            .flat_map(|index| self.get_valid_moves(Square(index as u8)))
+            .collect()
+    }
+
+    // Count the positions reached by playing every sequence of valid moves of a given length,
+    // to check the move generation against known counts
+    pub fn perft(&self, depth: u32) -> u64 {
+        self.clone().count_nodes(depth)
+    }
+
+    // Split the perft count by the first move, to find which move the generation gets wrong
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        let mut game = self.clone();
+        game.get_all_valid_moves()
+            .into_iter()
+            .map(|mv| {
+                let undo = game.make_move(mv);
+                let nodes = game.count_nodes(depth.saturating_sub(1));
+                game.unmake_move(mv, undo);
+                (mv, nodes)
+            })
+            .collect()
+    }
+
+    fn count_nodes(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.get_all_valid_moves();
+        // The moves themselves are the positions one move deep
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = self.make_move(mv);
+            nodes += self.count_nodes(depth - 1);
+            self.unmake_move(mv, undo);
+        }
+        nodes
     }
 
     // Print out the curent board and pieces
-    fn display_board(&self) {
-        let mut column = 8;
+    pub fn display_board(&self) {
         print!("\n\n   ");
-        for index in 0..8 {
-            print!("{} ", index + 1);
-        }
-        let mut row = 0;
-        for index in &self.board {
-            if column == 8 {
-                row = row + 1;
-                column = 0;
-                print!(
-                    "\n{}  ",
-                    match row {
-                        1 => "A",
-                        2 => "B",
-                        3 => "C",
-                        4 => "D",
-                        5 => "E",
-                        6 => "F",
-                        7 => "G",
-                        8 => "H",
-                        _ => " ",
-                    }
-                );
+        for file in b'a'..=b'h' {
+            print!("{} ", file as char);
+        }
+        for index in 0..64 {
+            // Tiles are numbered from rank 8
+            if index % 8 == 0 {
+                print!("\n{}  ", 8 - index / 8);
+            }
+            match self.piece(index) {
+                Some(piece) => print!("{} ", piece),
+                None => print!("  "),
             }
-            match index {
-                0b0001_0001 => print!("K "),
-                0b0000_1001 => print!("k "),
-                0b0001_0110 => print!("Q "),
-                0b0000_1110 => print!("q "),
-                0b0001_0101 => print!("B "),
-                0b0000_1101 => print!("b "),
-                0b0001_0100 => print!("N "),
-                0b0000_1100 => print!("n "),
-                0b0001_0011 => print!("R "),
-                0b0000_1011 => print!("r "),
-                0b0001_0010 => print!("P "),
-                0b0000_1010 => print!("p "),
-                _ => print!("  "),
-            }
-            column = column + 1;
         }
     }
 }
@@ -878,51 +1238,793 @@ mod tests {
 
     #[test]
     fn init_test_custom() {
-        let _game = Game::new_board("4k3/3Q3R/8/8/8/8/8/8 b KQkq - 0 1");
+        let _game = Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq - 0 1").unwrap();
+    }
+
+    #[test]
+    fn init_test_invalid_fen() {
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq").err(),
+            Some(FenError::WrongFieldCount(3))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3X3R/8/8/8/8/8/8 b KQkq - 0 1").err(),
+            Some(FenError::InvalidPiece('X'))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8 b KQkq - 0 1").err(),
+            Some(FenError::WrongRankCount(7))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/9/8/8/8 b KQkq - 0 1").err(),
+            Some(FenError::InvalidPiece('9'))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/45/8/8/8 b KQkq - 0 1").err(),
+            Some(FenError::InvalidRankLength(4))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkx - 0 1").err(),
+            Some(FenError::InvalidCastling("KQkx".to_string()))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KKq - 0 1").err(),
+            Some(FenError::InvalidCastling("KKq".to_string()))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq e6 0 1").err(),
+            Some(FenError::InvalidEnPassant("e6".to_string()))
+        );
+        // The target must be behind a pawn that could just have moved two tiles
+        let enpassant_fens = [
+            "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+            "4k3/8/8/3P4/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/3n4/3p4/8/8/8/4K3 w - d6 0 1",
+            "4k3/3n4/8/3p4/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/8/4P3/4N3/8/4K3 b - e3 0 1",
+        ];
+        for fen in enpassant_fens.iter() {
+            let square = fen.split_whitespace().nth(3).unwrap().to_string();
+            assert_eq!(Game::from_fen(fen).err(), Some(FenError::InvalidEnPassant(square)));
+        }
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq - x 1").err(),
+            Some(FenError::InvalidHalfmoveClock("x".to_string()))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq - 0 0").err(),
+            Some(FenError::InvalidFullmoveNumber("0".to_string()))
+        );
+        assert_eq!(
+            Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 x KQkq - 0 1").err(),
+            Some(FenError::InvalidTurn("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq - 12 31",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens.iter() {
+            assert_eq!(Game::from_fen(fen).unwrap().to_fen(), *fen);
+        }
+        assert_eq!(Game::new().to_fen(), fens[0]);
     }
 
     #[test]
     fn test_move() {
-        let mut a = Game::new_board("4k3/3Q3R/8/8/8/8/8/8 b KQkq - 0 1");
+        let mut a = Game::from_fen("4k3/3Q3R/8/8/8/8/8/8 b KQkq - 0 1").unwrap();
         // Test non-legal move
-        a.move_piece("A5", "A4");
+        assert_eq!(a.move_piece("e8d8".parse().unwrap()), Err(ChessError::IllegalMove));
 
         // Test legal move
-        a.move_piece("A5", "A6");
+        assert_eq!(a.move_piece("e8f8".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_move_errors() {
+        let mut game = Game::new();
+        assert_eq!(
+            "z9a1".parse::<Move>(),
+            Err(ChessError::InvalidSquare("z9".to_string()))
+        );
+        assert_eq!(game.move_piece("e4e5".parse().unwrap()), Err(ChessError::NoPieceOnSquare));
+        assert_eq!(game.move_piece("e7e6".parse().unwrap()), Err(ChessError::NotYourTurn));
+        assert_eq!(game.move_piece("e2e5".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("e2e4".parse().unwrap()), Ok(()));
+        assert_eq!(game.move_piece("d2d3".parse().unwrap()), Err(ChessError::NotYourTurn));
+        assert_eq!(game.get_turn(), Color::Black);
+        assert_eq!(
+            game.piece_at("e4".parse().unwrap()),
+            Some(Piece::new(Color::White, PieceKind::Pawn))
+        );
+        assert_eq!(game.piece_at("e2".parse().unwrap()), None);
     }
 
     #[test]
     fn test_promotion() {
-        let mut game = Game::new_board("8/P7/8/8/8/8/8/8 w - - 0 1");
-        game.move_piece("B1", "A1");
+        let mut game = Game::from_fen("8/P7/8/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("a7a8".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "Q7/8/8/8/8/8/8/8 b - - 0 1");
     }
 
     #[test]
     fn test_change_promotion() {
-        let mut game = Game::new_board("8/P7/8/8/8/8/8/8 w - - 0 1");
-        game.set_promotion('N');
-        game.move_piece("B1", "A1");
+        let mut game = Game::from_fen("8/P7/8/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("a7a8n".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "N7/8/8/8/8/8/8/8 b - - 0 1");
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/p7/4K3 b - - 0 1").unwrap();
+        let from: Square = "a2".parse().unwrap();
+        let to: Square = "a1".parse().unwrap();
+        assert_eq!(
+            game.move_piece(Move::new_promotion(from, to, PieceKind::Rook)),
+            Ok(())
+        );
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/r3K3 w - - 0 2");
+    }
+
+    #[test]
+    fn test_invalid_promotion() {
+        let mut game = Game::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.move_piece("a7a8k".parse().unwrap()),
+            Err(ChessError::InvalidPromotion(PieceKind::King))
+        );
+        assert_eq!(
+            game.move_piece("a7a8p".parse().unwrap()),
+            Err(ChessError::InvalidPromotion(PieceKind::Pawn))
+        );
+        // Only pawns reaching the last row may promote
+        assert_eq!(game.move_piece("e1e2q".parse().unwrap()), Err(ChessError::IllegalMove));
+    }
+
+    #[test]
+    fn test_promotion_moves() {
+        let game = Game::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves: Vec<String> = game
+            .get_valid_moves("a7".parse().unwrap())
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        assert_eq!(
+            moves,
+            ["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7b8q", "a7b8r", "a7b8b", "a7b8n"]
+        );
     }
 
     #[test]
     fn test_pawn_move() {
-        let mut game = Game::new_board("8/8/4pp2/4P3/8/8/8/8 w - - 0 1");
-        game.move_piece("D5", "C5");
-        game.move_piece("D5", "C6");
+        let mut game = Game::from_fen("8/8/4pp2/4P3/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("e5e6".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("e5f6".parse().unwrap()), Ok(()));
     }  
     #[test]
     fn test_check_state() {
-        let mut game = Game::new_board("8/p7/5k2/4P3/8/8/P7/8 b - - 0 1");
+        let mut game = Game::from_fen("8/p7/5k2/4P3/8/8/P7/8 b - - 0 1").unwrap();
         println!("\n{}\n", game.to_fen());
-        game.move_piece("B1", "D1");
+        // The pawn on e5 attacks the black king
+        assert_eq!(game.move_piece("a7a5".parse().unwrap()), Err(ChessError::IllegalMove));
         println!("\n{}\n", game.to_fen());
-        game.move_piece("C6", "D5");
+        assert_eq!(game.move_piece("f6e5".parse().unwrap()), Ok(()));
         println!("\n{}\n", game.to_fen());
-        game.move_piece("G1", "F1");
+        assert_eq!(game.move_piece("a2a3".parse().unwrap()), Ok(()));
         println!("\n{}\n", game.to_fen());
-        game.move_piece("B1", "B2");
+        assert_eq!(game.move_piece("a7b7".parse().unwrap()), Err(ChessError::IllegalMove));
         println!("\n{}\n", game.to_fen());
-        game.move_piece("B1", "C1");
+        assert_eq!(game.move_piece("a7a6".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_check_detection() {
+        let mut game = Game::new();
+        for mv in ["e2e4", "d7d6"].iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+            assert_eq!(game.get_state(), GameState::InProgress);
+        }
+        assert_eq!(game.move_piece("f1b5".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Check);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap();
+        assert_eq!(game.get_state(), GameState::Check);
+    }
+
+    #[test]
+    fn test_check_responses() {
+        // The rook on e8 checks the king, which the bishop may block or the knight capture
+        let fen = "4r2k/8/8/8/5N2/8/3B4/4K3 w - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.get_state(), GameState::Check);
+        let mut moves: Vec<String> = game
+            .get_valid_moves("d2".parse().unwrap())
+            .iter()
+            .map(|mv| mv.to.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["e3"]);
+        let knight_moves = game.get_valid_moves("f4".parse().unwrap());
+        assert_eq!(knight_moves.len(), 2);
+        assert!(knight_moves.contains(&"f4e2".parse().unwrap()));
+        assert!(knight_moves.contains(&"f4e6".parse().unwrap()));
+
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.move_piece("d2b4".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("d2e3".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::InProgress);
+
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.move_piece("e1d1".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::InProgress);
+    }
+
+    #[test]
+    fn test_double_check() {
+        // The rook and the bishop both check the king, so only the king may move
+        let game = Game::from_fen("4r2k/8/8/8/7b/2Q5/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.get_state(), GameState::Check);
+        assert!(game.get_valid_moves("c3".parse().unwrap()).is_empty());
+        assert!(!game.get_valid_moves("e1".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_checkmate() {
+        let mut game = Game::new();
+        for mv in ["f2f3", "e7e5", "g2g4"].iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+        }
+        assert_eq!(game.get_winner(), None);
+        assert_eq!(game.move_piece("d8h4".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Checkmate);
+        assert_eq!(game.get_winner(), Some(Color::Black));
+        assert_eq!(game.move_piece("a2a3".parse().unwrap()), Err(ChessError::GameOver));
+
+        let game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(game.get_state(), GameState::Checkmate);
+        assert_eq!(game.get_winner(), Some(Color::White));
+    }
+
+    #[test]
+    fn test_stalemate() {
+        let mut game = Game::from_fen("7k/8/5Q2/8/8/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("f6g6".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Stalemate);
+        assert_eq!(game.get_winner(), None);
+        assert_eq!(game.move_piece("h8h7".parse().unwrap()), Err(ChessError::GameOver));
+    }
+
+    #[test]
+    fn test_move_rule_draws() {
+        // The fifty-move rule may be claimed, but play goes on
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert_eq!(game.move_piece("a1a2".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::FiftyMoveRule));
+        assert!(!game.is_game_over());
+        assert!(game.can_claim_draw());
+        assert_eq!(game.move_piece("e8e7".parse().unwrap()), Ok(()));
+
+        // After seventy-five moves the game is drawn
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 100").unwrap();
+        assert_eq!(game.move_piece("a1a2".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::SeventyFiveMoveRule));
+        assert_eq!(game.move_piece("e8e7".parse().unwrap()), Err(ChessError::GameOver));
+
+        // A pawn move resets the count
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/P7/R3K3 w - - 99 80").unwrap();
+        assert_eq!(game.move_piece("a2a3".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::InProgress);
+    }
+
+    #[test]
+    fn test_repetition_draws() {
+        let mut game = Game::new();
+        let knight_moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for _ in 0..2 {
+            for mv in knight_moves.iter() {
+                assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+            }
+        }
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::ThreefoldRepetition));
+        assert!(!game.is_game_over());
+        for _ in 0..2 {
+            for mv in knight_moves.iter() {
+                assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+            }
+        }
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::FivefoldRepetition));
+        assert_eq!(game.move_piece("e2e4".parse().unwrap()), Err(ChessError::GameOver));
+
+        // Positions with different castling rights are not the same
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        for mv in ["a1a2", "e8e7", "a2a1", "e7e8", "a1a2", "e8e7", "a2a1", "e7e8"].iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+        }
+        assert_eq!(game.get_state(), GameState::InProgress);
+        for mv in ["a1a2", "e8e7", "a2a1", "e7e8"].iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+        }
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::ThreefoldRepetition));
+        assert!(game.can_claim_draw());
+
+        // A repeated check is still reported as check, with the draw left to be claimed
+        let mut game = Game::from_fen("7k/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let moves = ["a1a8", "h8h7", "a8a7", "h7h8", "a7a8", "h8h7", "a8a7", "h7h8", "a7a8"];
+        for mv in moves.iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+        }
+        assert_eq!(game.get_state(), GameState::Check);
+        assert!(game.can_claim_draw());
+        assert!(!game.checkers().is_empty());
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 120 80").unwrap();
+        assert_eq!(game.get_state(), GameState::Check);
+        assert!(game.can_claim_draw());
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let drawn = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+        ];
+        for fen in drawn.iter() {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(game.get_state(), GameState::Draw(DrawReason::InsufficientMaterial));
+            assert!(game.is_game_over());
+        }
+        let playable = [
+            "4kb2/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/P7/4K3 w - - 0 1",
+        ];
+        for fen in playable.iter() {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(game.get_state(), GameState::InProgress);
+        }
+
+        // Capturing the last piece that could mate ends the game
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1d2".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Draw(DrawReason::InsufficientMaterial));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::new();
+        assert_eq!(game.undo(), None);
+        let moves = ["e2e4", "d7d5", "e4d5", "d8d5"];
+        let mut fens = vec![game.to_fen()];
+        for mv in moves.iter() {
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+            fens.push(game.to_fen());
+        }
+        assert_eq!(game.history().len(), 4);
+        assert_eq!(game.history()[2].captured, "p".parse().ok());
+        assert_eq!(game.history()[2].enpassant, "d6".parse().ok());
+
+        for fen in fens.iter().rev().skip(1) {
+            assert!(game.undo().is_some());
+            assert_eq!(&game.to_fen(), fen);
+        }
+        assert_eq!(game.undo(), None);
+        for fen in fens.iter().skip(1) {
+            assert!(game.redo().is_some());
+            assert_eq!(&game.to_fen(), fen);
+        }
+        assert_eq!(game.redo(), None);
+
+        // Playing a new move after taking one back forgets the moves taken back
+        assert_eq!(game.undo(), "d8d5".parse().ok());
+        assert_eq!(game.move_piece("g8f6".parse().unwrap()), Ok(()));
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn test_undo_special_moves() {
+        let fens = [
+            // Castling
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 3 10", "e1c1"),
+            // En passant
+            ("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 20", "d5e6"),
+            // Promotion with a capture
+            ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 40", "a7b8n"),
+        ];
+        for (fen, mv) in fens.iter() {
+            let mut game = Game::from_fen(fen).unwrap();
+            assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+            assert_eq!(game.undo(), mv.parse().ok());
+            assert_eq!(&game.to_fen(), fen);
+        }
+
+        // The game goes on again after the mating move is taken back
+        let mut game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(game.move_piece("a1a8".parse().unwrap()), Ok(()));
+        assert_eq!(game.get_state(), GameState::Checkmate);
+        game.undo();
+        assert_eq!(game.get_state(), GameState::InProgress);
+        assert_eq!(game.get_winner(), None);
+    }
+
+    #[test]
+    fn test_make_unmake_random() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ];
+        // Xorshift, so the walks are the same on every run
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for fen in fens.iter() {
+            for _ in 0..20 {
+                let mut game = Game::from_fen(fen).unwrap();
+                let mut played = Vec::new();
+                for _ in 0..40 {
+                    let moves = game.get_all_valid_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let mv = moves[random() as usize % moves.len()];
+                    let before = (game.pieces, game.to_fen(), game.positions.clone());
+                    let undo = game.make_move(mv);
+                    assert_eq!(game.full_hash(), game.zobrist_key());
+                    played.push((mv, undo, before));
+                }
+                while let Some((mv, undo, before)) = played.pop() {
+                    game.unmake_move(mv, undo);
+                    assert_eq!((game.pieces, game.to_fen(), game.positions.clone()), before);
+                    assert_eq!(game.full_hash(), game.zobrist_key());
+                }
+                assert_eq!(game.get_state(), Game::from_fen(fen).unwrap().get_state());
+            }
+        }
+    }
+
+    // Perft positions from the chess programming wiki with their node counts by depth
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_4_MIRRORED: &str =
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn assert_perft(fen: &str, counts: &[u64]) {
+        let game = Game::from_fen(fen).unwrap();
+        for (depth, count) in counts.iter().enumerate() {
+            assert_eq!(game.perft(depth as u32 + 1), *count, "{} at depth {}", fen, depth + 1);
+        }
+    }
+
+    #[test]
+    fn test_perft() {
+        assert_eq!(Game::new().perft(0), 1);
+        assert_perft(START, &[20, 400, 8902]);
+        assert_perft(KIWIPETE, &[48, 2039, 97862]);
+        assert_perft(POSITION_3, &[14, 191, 2812, 43238]);
+        assert_perft(POSITION_4, &[6, 264, 9467]);
+        assert_perft(POSITION_4_MIRRORED, &[6, 264, 9467]);
+        assert_perft(POSITION_5, &[44, 1486, 62379]);
+        assert_perft(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    // These take a while without optimizations, run them with cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn test_perft_deep() {
+        assert_eq!(Game::from_fen(START).unwrap().perft(5), 4_865_609);
+        assert_eq!(Game::from_fen(KIWIPETE).unwrap().perft(4), 4_085_603);
+        assert_eq!(Game::from_fen(POSITION_3).unwrap().perft(5), 674_624);
+        assert_eq!(Game::from_fen(POSITION_4).unwrap().perft(4), 422_333);
+        assert_eq!(Game::from_fen(POSITION_5).unwrap().perft(4), 2_103_487);
+        assert_eq!(Game::from_fen(POSITION_6).unwrap().perft(4), 3_894_594);
+    }
+
+    #[test]
+    fn test_divide() {
+        let game = Game::from_fen(KIWIPETE).unwrap();
+        let divide = game.divide(2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        let castle = divide.iter().find(|(mv, _)| mv.to_string() == "e1g1");
+        assert_eq!(castle.map(|(_, nodes)| *nodes), Some(43));
+        // The game is left as it was
+        assert_eq!(game.to_fen(), KIWIPETE);
+    }
+
+    #[test]
+    fn test_zobrist_keys() {
+        // Keys from the Polyglot book format specification, reached by playing the moves
+        let lines = [
+            vec![
+                ("", 0x463b_9618_1691_fc9c),
+                ("e2e4", 0x823c_9b50_fd11_4196),
+                ("d7d5", 0x0756_b944_61c5_0fb0),
+                ("e4e5", 0x662f_afb9_65db_29d4),
+                ("f7f5", 0x22a4_8b5a_8e47_ff78),
+                ("e1e2", 0x652a_607c_a3f2_42c1),
+                ("e8f7", 0x00fd_d303_c946_bdd9),
+            ],
+            vec![
+                ("", 0x463b_9618_1691_fc9c),
+                ("a2a4", 0),
+                ("b7b5", 0),
+                ("h2h4", 0),
+                ("b5b4", 0),
+                ("c2c4", 0x3c81_23ea_7b06_7637),
+                ("b4c3", 0),
+                ("a1a3", 0x5c3f_9b82_9b27_9560),
+            ],
+        ];
+        for line in lines.iter() {
+            let mut game = Game::new();
+            for (mv, key) in line.iter() {
+                if !mv.is_empty() {
+                    assert_eq!(game.move_piece(mv.parse().unwrap()), Ok(()));
+                }
+                if *key != 0 {
+                    assert_eq!(game.zobrist_key(), *key, "after {}", mv);
+                    let fen = game.to_fen();
+                    assert_eq!(Game::from_fen(&fen).unwrap().zobrist_key(), *key, "{}", fen);
+                }
+            }
+            while game.undo().is_some() {}
+            assert_eq!(game.zobrist_key(), 0x463b_9618_1691_fc9c);
+        }
+
+        // An en passant target no pawn can capture on does not change the key
+        let game = Game::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+        let without = Game::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(game.zobrist_key(), without.zobrist_key());
+
+        // A pawn beside the target counts even when it is pinned, but not for repetitions
+        let game = Game::from_fen("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1").unwrap();
+        let without = Game::from_fen("8/8/8/K2pP2r/8/8/8/4k3 w - - 0 1").unwrap();
+        assert_eq!(game.zobrist_key() ^ without.zobrist_key(), zobrist::enpassant_key(3));
+        assert_eq!(game.positions, without.positions);
+        let game = Game::from_fen("8/8/8/K2pP3/8/8/8/4k3 w - d6 0 1").unwrap();
+        let without = Game::from_fen("8/8/8/K2pP3/8/8/8/4k3 w - - 0 1").unwrap();
+        assert_ne!(game.positions, without.positions);
+    }
+
+    #[test]
+    fn test_legal_move_queries() {
+        let game = Game::new();
+        assert_eq!(game.all_legal_moves().len(), 20);
+        assert_eq!(game.legal_moves_from("b1".parse().unwrap()).len(), 2);
+        assert!(game.legal_moves_from("e7".parse().unwrap()).is_empty());
+        assert!(game.legal_moves_from("e4".parse().unwrap()).is_empty());
+        assert!(game.is_legal("e2e4".parse().unwrap()));
+        assert!(!game.is_legal("e2e5".parse().unwrap()));
+        assert!(!game.is_legal("e7e5".parse().unwrap()));
+
+        let flags = |game: &Game, uci: &str| {
+            let mv: Move = uci.parse().unwrap();
+            let found = game.all_legal_moves().into_iter().find(|other| *other == mv);
+            found.map(|mv| mv.flags)
+        };
+        let game = Game::from_fen("r3k3/1P6/8/3Pp3/8/8/8/R3K2R w KQq e6 0 1").unwrap();
+        let quiet = MoveFlags::default();
+        assert_eq!(flags(&game, "h1h2"), Some(quiet));
+        let en_passant = MoveFlags {
+            capture: true,
+            en_passant: true,
+            ..quiet
+        };
+        assert_eq!(flags(&game, "d5e6"), Some(en_passant));
+        let castle = MoveFlags {
+            castle: true,
+            ..quiet
+        };
+        assert_eq!(flags(&game, "e1g1"), Some(castle));
+        let capture_promotion = MoveFlags {
+            capture: true,
+            check: true,
+            promotion: true,
+            ..quiet
+        };
+        assert_eq!(flags(&game, "b7a8q"), Some(capture_promotion));
+        let check = MoveFlags {
+            check: true,
+            ..quiet
+        };
+        assert_eq!(flags(&game, "h1h8"), Some(check));
+        assert!(!game.is_legal("b7b8".parse().unwrap()));
+        assert!(game.is_legal("b7b8n".parse().unwrap()));
+
+        // Nothing may be played once the game is over
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(game.all_legal_moves().is_empty());
+        assert!(!game.is_legal("e1e2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_attack_queries() {
+        let square = |name: &str| name.parse::<Square>().unwrap();
+        let game = Game::new();
+        let mut attackers = game.attackers_of(square("f3"), Color::White);
+        attackers.sort_by_key(|square| square.to_string());
+        assert_eq!(attackers, vec![square("e2"), square("g1"), square("g2")]);
+        assert!(game.is_square_attacked(square("d6"), Color::Black));
+        assert!(!game.is_square_attacked(square("d5"), Color::Black));
+        // Pawns attack diagonally even when they can not move there
+        assert!(game.is_square_attacked(square("a3"), Color::White));
+        assert!(game.checkers().is_empty());
+
+        let game = Game::from_fen("4r2k/8/8/8/7b/2Q5/8/4K3 w - - 0 1").unwrap();
+        let mut checkers = game.checkers();
+        checkers.sort_by_key(|square| square.to_string());
+        assert_eq!(checkers, vec![square("e8"), square("h4")]);
+
+        // The knight is pinned by the bishop, but the pawns are not pinned by the rook since
+        // both stand between it and the king
+        let game = Game::from_fen("4k3/8/4r3/8/1b6/2N1P3/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.pinned_pieces(Color::White), vec![square("c3")]);
+        assert!(game.pinned_pieces(Color::Black).is_empty());
+    }
+
+    #[test]
+    fn test_castling() {
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1g1".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+        assert_eq!(game.move_piece("e8c8".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+    }
+
+    #[test]
+    fn test_castling_requirements() {
+        // A piece stands between the king and the queenside rook
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1c1".parse().unwrap()), Err(ChessError::IllegalMove));
+
+        // The king is in check
+        let mut game = Game::from_fen("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1g1".parse().unwrap()), Err(ChessError::IllegalMove));
+
+        // The king would pass an attacked tile
+        let mut game = Game::from_fen("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1g1".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("e1c1".parse().unwrap()), Ok(()));
+
+        // The king would land on an attacked tile
+        let mut game = Game::from_fen("6rk/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1g1".parse().unwrap()), Err(ChessError::IllegalMove));
+
+        // Only the rook on b1 is attacked, which does not prevent castling
+        let mut game = Game::from_fen("1r4k1/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1c1".parse().unwrap()), Ok(()));
+
+        // The right to castle has already been lost
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1").unwrap();
+        assert_eq!(game.move_piece("e1g1".parse().unwrap()), Err(ChessError::IllegalMove));
+    }
+
+    #[test]
+    fn test_castling_rights() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+        // Moving the king loses both rights
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.move_piece("e1e2".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/4K3/R6R b kq - 1 1");
+
+        // Moving a rook loses the right on its side
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.move_piece("a1a2".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/R7/4K2R b Kkq - 1 1");
+
+        // Having a rook captured loses the right on its side
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.move_piece("h1h8".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "r3k2R/8/8/8/8/8/8/R3K3 b Qq - 0 1");
+    }
+
+    #[test]
+    fn test_enpassant() {
+        let mut game = Game::new();
+        assert_eq!(game.move_piece("e2e4".parse().unwrap()), Ok(()));
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(game.move_piece("a7a6".parse().unwrap()), Ok(()));
+        assert_eq!(game.move_piece("e4e5".parse().unwrap()), Ok(()));
+        assert_eq!(game.move_piece("d7d5".parse().unwrap()), Ok(()));
+        let fen = "rnbqkbnr/1pp1pppp/p7/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+        assert_eq!(game.move_piece("e5d6".parse().unwrap()), Ok(()));
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/1pp1pppp/p2P4/8/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3"
+        );
+    }
+
+    #[test]
+    fn test_enpassant_expires() {
+        let mut game = Game::from_fen("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(game.move_piece("d7d5".parse().unwrap()), Ok(()));
+        assert_eq!(game.move_piece("e1e2".parse().unwrap()), Ok(()));
+        assert_eq!(game.move_piece("e8e7".parse().unwrap()), Ok(()));
+        assert_eq!(game.to_fen(), "8/4k3/8/3pP3/8/8/4K3/8 w - - 2 3");
+        assert_eq!(game.move_piece("e5d6".parse().unwrap()), Err(ChessError::IllegalMove));
+    }
+
+    #[test]
+    fn test_enpassant_pin() {
+        // Capturing en passant would remove both pawns from the row between king and rook
+        let mut game = Game::from_fen("4k3/8/8/K2pP2r/8/8/8/8 w - d6 0 1").unwrap();
+        assert_eq!(game.move_piece("e5d6".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("e5e6".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_pinned_pieces() {
+        // The bishop on e2 is pinned against its king by the rook on e7
+        let mut game = Game::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+        assert!(game.get_valid_moves("e2".parse().unwrap()).is_empty());
+        assert_eq!(game.move_piece("e2d3".parse().unwrap()), Err(ChessError::IllegalMove));
+
+        // A pinned rook may still move along the pinning line
+        let game = Game::from_fen("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1").unwrap();
+        let mut moves: Vec<String> = game
+            .get_valid_moves("e2".parse().unwrap())
+            .iter()
+            .map(|mv| mv.to.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["e3", "e4", "e5", "e6", "e7"]);
+    }
+
+    #[test]
+    fn test_discovered_check() {
+        // Moving the knight would uncover the bishop's attack on the white king
+        let mut game = Game::from_fen("4k3/8/8/b7/8/2N5/8/4K3 w - - 0 1").unwrap();
+        assert!(game.get_valid_moves("c3".parse().unwrap()).is_empty());
+        assert_eq!(game.move_piece("c3d5".parse().unwrap()), Err(ChessError::IllegalMove));
+        assert_eq!(game.move_piece("e1f2".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_king_attacked_tiles() {
+        // The pawn on d3 attacks e2 and the king may not stay next to the other king
+        let game = Game::from_fen("8/8/8/8/8/3p4/8/4K1k1 w - - 0 1").unwrap();
+        let mut moves: Vec<String> = game
+            .get_valid_moves("e1".parse().unwrap())
+            .iter()
+            .map(|mv| mv.to.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["d1", "d2"]);
+    }
+
+    #[test]
+    fn test_board_edges() {
+        // Pieces on the edge of the board must not wrap around to the other side
+        let game = Game::from_fen("7k/8/8/8/8/8/8/N6K w - - 0 1").unwrap();
+        let mut moves: Vec<String> = game
+            .get_valid_moves("a1".parse().unwrap())
+            .iter()
+            .map(|mv| mv.to.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["b3", "c2"]);
+        let game = Game::from_fen("7k/8/8/8/8/8/8/R6K w - - 0 1").unwrap();
+        assert_eq!(game.get_valid_moves("a1".parse().unwrap()).len(), 13);
     }  
 
 }
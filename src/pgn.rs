@@ -0,0 +1,856 @@
+// Portable Game Notation, reading and writing games with their tags, comments, annotations and
+// variations
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::tree::{GameNode, GameTree};
+use crate::{ChessError, Color, FenError, Game, GameState, STARTING_FEN};
+
+// Movetext lines are kept within this many characters
+const LINE_WIDTH: usize = 80;
+
+// Tokens that end the movetext of a game
+pub(crate) const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// PgnError describes why PGN text could not be read into a game
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    InvalidTag(String),
+    UnexpectedToken(String),
+    UnterminatedComment,
+    UnbalancedVariation,
+    InvalidFen(FenError),
+    InvalidMove(String, ChessError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Io(error) => write!(f, "could not read PGN: {}", error),
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair '{}' in PGN", tag),
+            PgnError::UnexpectedToken(token) => write!(f, "unexpected '{}' in PGN", token),
+            PgnError::UnterminatedComment => write!(f, "comment in PGN is never closed"),
+            PgnError::UnbalancedVariation => {
+                write!(f, "variation in PGN is not opened and closed in pairs")
+            }
+            PgnError::InvalidFen(error) => write!(f, "invalid FEN tag in PGN: {}", error),
+            PgnError::InvalidMove(san, error) => {
+                write!(f, "could not play '{}' from PGN: {}", san, error)
+            }
+        }
+    }
+}
+
+impl Error for PgnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PgnError::Io(error) => Some(error),
+            PgnError::InvalidFen(error) => Some(error),
+            PgnError::InvalidMove(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PgnError {
+    fn from(error: io::Error) -> PgnError {
+        PgnError::Io(error)
+    }
+}
+
+impl GameTree {
+    // Read a single game from PGN text. The moves are played from the position of the FEN
+    // tag, or the standard starting position without one, and must all be legal
+    pub fn from_pgn(pgn: &str) -> Result<GameTree, PgnError> {
+        let mut tokens = Tokens::new(pgn).collect::<Result<Vec<Token>, PgnError>>()?;
+        let movetext = tokens
+            .iter()
+            .position(|token| !matches!(token, Token::Tag(..)))
+            .unwrap_or(tokens.len());
+        let movetext = tokens.split_off(movetext);
+
+        let mut tags = Vec::new();
+        for token in tokens {
+            if let Token::Tag(name, value) = token {
+                tags.push((name, value));
+            }
+        }
+        let position = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Game::from_fen(fen).map_err(PgnError::InvalidFen)?,
+            None => Game::new(),
+        };
+
+        let mut tree = GameTree::new(position);
+        for (name, value) in tags.iter() {
+            tree.set_tag(name, value);
+        }
+        let mut movetext = movetext.into_iter();
+        let result = parse_line(&mut movetext, tree.root_mut(), false)?;
+        // The result token ends the game, so another game may not follow it
+        if let Some(token) = movetext.next() {
+            return Err(PgnError::UnexpectedToken(token.to_string()));
+        }
+        // Without a result token the Result tag is the best guess
+        let result = result
+            .or_else(|| {
+                tree.tag("Result")
+                    .filter(|result| RESULTS.contains(result))
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| "*".to_string());
+        tree.set_result(&result).expect("only PGN results are read");
+        Ok(tree)
+    }
+
+    // Write the game as PGN with its tags, comments, NAGs and variations, so that reading it
+    // back gives the same tree
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in self.tags() {
+            write_tag(&mut pgn, name, value);
+        }
+        // Trees built from a custom position need it to be read back
+        let root = self.root();
+        let fen = root.position().to_fen();
+        if fen != STARTING_FEN && self.tag("FEN").is_none() {
+            if self.tag("SetUp").is_none() {
+                write_tag(&mut pgn, "SetUp", "1");
+            }
+            write_tag(&mut pgn, "FEN", &fen);
+        }
+        if !pgn.is_empty() {
+            pgn.push('\n');
+        }
+        let mut tokens: Vec<String> = root.comments().iter().map(|text| comment(text)).collect();
+        write_variations(root, true, &mut tokens);
+        tokens.push(self.result().to_string());
+        write_movetext(&mut pgn, &tokens);
+        pgn
+    }
+}
+
+// Write the moves after a node, each main line move followed by its variations in parentheses.
+// The first move is numbered even for Black when `force_number` is set
+fn write_variations(mut parent: &GameNode, mut force_number: bool, tokens: &mut Vec<String>) {
+    while let Some((main, others)) = parent.variations().split_first() {
+        force_number = write_move(parent, main, force_number, tokens);
+        for variation in others {
+            let mut line = Vec::new();
+            let force_next = write_move(parent, variation, true, &mut line);
+            write_variations(variation, force_next, &mut line);
+            line[0].insert(0, '(');
+            // A comment to the end of the line would swallow the closing parenthesis
+            let last = line.last_mut().expect("a move was written");
+            if is_line_comment(last) {
+                line.push(")".to_string());
+            } else {
+                last.push(')');
+            }
+            tokens.append(&mut line);
+            force_number = true;
+        }
+        parent = main;
+    }
+}
+
+// Write a move with its number and annotations. Black's moves are numbered when they do not
+// directly follow White's move. Returns whether the next move needs its number
+fn write_move(
+    parent: &GameNode,
+    node: &GameNode,
+    force_number: bool,
+    tokens: &mut Vec<String>,
+) -> bool {
+    tokens.extend(node.starting_comments().iter().map(|text| comment(text)));
+    let position = parent.position();
+    let mv = node.mv().expect("only the root has no move");
+    if position.turn == Color::White {
+        tokens.push(format!("{}.", position.fullmove));
+    } else if force_number || !node.starting_comments().is_empty() {
+        tokens.push(format!("{}...", position.fullmove));
+    }
+    tokens.push(position.to_san(mv).expect("moves in the tree are legal"));
+    tokens.extend(node.nags().iter().map(|nag| format!("${}", nag)));
+    tokens.extend(node.comments().iter().map(|text| comment(text)));
+    !node.comments().is_empty()
+}
+
+// Write a comment in braces, or to the end of the line when it holds a "}" that would close
+// the braces
+fn comment(text: &str) -> String {
+    if text.contains('}') {
+        format!("; {}", text)
+    } else {
+        format!("{{{}}}", text)
+    }
+}
+
+// Check if a token is a comment to the end of the line, which may open a variation
+fn is_line_comment(token: &str) -> bool {
+    token.trim_start_matches('(').starts_with(';')
+}
+
+// The Seven Tag Roster every PGN game starts with. Unknown values are written as "?", and dates
+// as "YYYY.MM.DD"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnTags {
+    pub event: String,
+    pub site: String,
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+}
+
+impl Default for PgnTags {
+    fn default() -> PgnTags {
+        PgnTags {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            date: "????.??.??".to_string(),
+            round: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+        }
+    }
+}
+
+impl Game {
+    // Write the moves played as a PGN game. Games that did not start from the standard
+    // position also get SetUp and FEN tags
+    pub fn to_pgn(&self, tags: &PgnTags) -> String {
+        let result = self.result_token();
+        let mut pgn = String::new();
+        write_tag(&mut pgn, "Event", &tags.event);
+        write_tag(&mut pgn, "Site", &tags.site);
+        write_tag(&mut pgn, "Date", &tags.date);
+        write_tag(&mut pgn, "Round", &tags.round);
+        write_tag(&mut pgn, "White", &tags.white);
+        write_tag(&mut pgn, "Black", &tags.black);
+        write_tag(&mut pgn, "Result", result);
+        if let Some(fen) = &self.start {
+            write_tag(&mut pgn, "SetUp", "1");
+            write_tag(&mut pgn, "FEN", fen);
+        }
+        pgn.push('\n');
+
+        // Replay the game to write each move as it was seen in its position
+        let mut replay = match &self.start {
+            Some(fen) => Game::from_fen(fen).expect("starting FEN was read before"),
+            None => Game::new(),
+        };
+        let mut tokens = Vec::new();
+        for (ply, entry) in self.history.iter().enumerate() {
+            if replay.turn == Color::White {
+                tokens.push(format!("{}.", replay.fullmove));
+            } else if ply == 0 {
+                tokens.push(format!("{}...", replay.fullmove));
+            }
+            tokens.push(replay.to_san(entry.mv).expect("played moves are legal"));
+            replay.move_piece(entry.mv).expect("played moves are legal");
+        }
+        tokens.push(result.to_string());
+        write_movetext(&mut pgn, &tokens);
+        pgn
+    }
+
+    // Get the PGN result of the game. Draws that may only be claimed leave the game going on
+    fn result_token(&self) -> &'static str {
+        match self.state {
+            GameState::Checkmate => match self.winner {
+                Some(Color::White) => "1-0",
+                Some(Color::Black) => "0-1",
+                None => "*",
+            },
+            GameState::Stalemate | GameState::Draw(_) if self.is_game_over() => "1/2-1/2",
+            _ => "*",
+        }
+    }
+}
+
+// Write a tag pair, escaping quotes and backslashes in the value
+fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+// Write movetext tokens separated by spaces, starting a new line before a token that would
+// not fit
+fn write_movetext(pgn: &mut String, tokens: &[String]) {
+    let mut width = 0;
+    for token in tokens {
+        if width > 0 && width + 1 + token.len() > LINE_WIDTH {
+            pgn.push('\n');
+            width = 0;
+        } else if width > 0 {
+            pgn.push(' ');
+            width += 1;
+        }
+        pgn.push_str(token);
+        width += token.len();
+        // Comments to the end of the line must have the line to themselves
+        if is_line_comment(token) {
+            pgn.push('\n');
+            width = 0;
+        }
+    }
+    if width > 0 {
+        pgn.push('\n');
+    }
+}
+
+// Reads the games of a PGN file one at a time, so large databases need not be read at once
+pub struct PgnReader<R> {
+    lines: io::Lines<R>,
+    // The first line of the next game, read while looking for the end of the last one
+    next_line: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> PgnReader<R> {
+        PgnReader {
+            lines: reader.lines(),
+            next_line: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<GameTree, PgnError>;
+
+    // A game ends where the tags of the next one start after its movetext. Brackets inside
+    // comments do not count
+    fn next(&mut self) -> Option<Result<GameTree, PgnError>> {
+        let mut text = String::new();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        loop {
+            let line = match self.next_line.take() {
+                Some(line) => line,
+                None => match self.lines.next() {
+                    Some(Ok(line)) => line,
+                    Some(Err(error)) => return Some(Err(PgnError::Io(error))),
+                    None => break,
+                },
+            };
+            let trimmed = line.trim();
+            if !in_comment {
+                if trimmed.starts_with('[') {
+                    if in_movetext {
+                        self.next_line = Some(line);
+                        break;
+                    }
+                } else if !trimmed.is_empty() && !trimmed.starts_with('%') {
+                    in_movetext = true;
+                }
+            }
+            // Find where a comment to the end of the line starts
+            let mut code_end = line.len();
+            for (index, c) in line.char_indices() {
+                match c {
+                    '{' if !in_comment => in_comment = true,
+                    '}' if in_comment => in_comment = false,
+                    ';' if !in_comment => {
+                        code_end = index;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            text.push_str(&line);
+            text.push('\n');
+            // Games without tags follow each other directly, so a result token also ends one
+            let last_word = line[..code_end].split_whitespace().last();
+            if in_movetext && !in_comment && last_word.is_some_and(|word| RESULTS.contains(&word))
+            {
+                break;
+            }
+        }
+        if text.trim().is_empty() {
+            None
+        } else {
+            Some(GameTree::from_pgn(&text))
+        }
+    }
+}
+
+// Read the moves of a line from the position of `parent` until the line ends, returning the
+// result token if the game ends there. Variations are read as other moves from the position the
+// last move was played from
+fn parse_line<I: Iterator<Item = Token>>(
+    tokens: &mut I,
+    mut parent: &mut GameNode,
+    nested: bool,
+) -> Result<Option<String>, PgnError> {
+    // The node of the last move, as an index into the variations of `parent`
+    let mut current: Option<usize> = None;
+    // Comments before the first move of a variation
+    let mut pending = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Move(word) => {
+                if let Some(index) = current {
+                    parent = &mut parent.children_mut()[index];
+                }
+                let san = word.trim_end_matches(['!', '?']);
+                let nag = annotation_nag(&word[san.len()..])
+                    .ok_or_else(|| PgnError::UnexpectedToken(word.clone()))?;
+                let mut position = parent.position().clone();
+                let mv = position
+                    .play_san(san)
+                    .map_err(|error| PgnError::InvalidMove(san.to_string(), error))?;
+                let node = parent.add_variation(position, mv);
+                node.starting_comments_mut().append(&mut pending);
+                node.nags_mut().extend(nag);
+                current = Some(parent.variations().len() - 1);
+            }
+            // Comments before the first move of the game belong to the game itself
+            Token::Comment(text) => match current {
+                Some(index) => parent.children_mut()[index].comments_mut().push(text),
+                None if !nested => parent.comments_mut().push(text),
+                None => pending.push(text),
+            },
+            Token::Nag(nag) => match current {
+                Some(index) => parent.children_mut()[index].nags_mut().push(nag),
+                None => return Err(PgnError::UnexpectedToken(format!("${}", nag))),
+            },
+            Token::Open => {
+                if current.is_none() {
+                    return Err(PgnError::UnexpectedToken("(".to_string()));
+                }
+                parse_line(tokens, parent, true)?;
+            }
+            Token::Close => {
+                if !nested {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                if current.is_none() {
+                    return Err(PgnError::UnexpectedToken(")".to_string()));
+                }
+                return Ok(None);
+            }
+            Token::Result(result) => {
+                if nested {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                return Ok(Some(result));
+            }
+            Token::Tag(name, _) => return Err(PgnError::UnexpectedToken(format!("[{}", name))),
+        }
+    }
+    if nested {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    Ok(None)
+}
+
+// Get the NAG of a move suffix such as "!?", or nothing when there is no suffix
+fn annotation_nag(suffix: &str) -> Option<Option<u8>> {
+    match suffix {
+        "" => Some(None),
+        "!" => Some(Some(1)),
+        "?" => Some(Some(2)),
+        "!!" => Some(Some(3)),
+        "??" => Some(Some(4)),
+        "!?" => Some(Some(5)),
+        "?!" => Some(Some(6)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(String),
+    Move(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Tag(name, value) => write!(f, "[{} \"{}\"]", name, value),
+            Token::Comment(text) => write!(f, "{{{}}}", text),
+            Token::Nag(nag) => write!(f, "${}", nag),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Result(word) | Token::Move(word) => write!(f, "{}", word),
+        }
+    }
+}
+
+// Splits PGN text into tokens, leaving out move numbers and escaped lines
+struct Tokens<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Tokens<'a> {
+        Tokens { text, position: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    // Skip to the end of the line
+    fn skip_line(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find('\n').unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+
+    // Read a tag pair such as [Event "F/S Return Match"], where the value may escape quotes and
+    // backslashes
+    fn tag(&mut self) -> Result<Token, PgnError> {
+        let rest = self.rest();
+        let invalid = || {
+            let line = rest.lines().next().unwrap_or("");
+            PgnError::InvalidTag(line.to_string())
+        };
+        let mut chars = rest.char_indices().skip(1).peekable();
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let mut name = String::new();
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        if name.is_empty() || chars.next().map(|(_, c)| c) != Some('"') {
+            return Err(invalid());
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next().map(|(_, c)| c) {
+                Some('"') => break,
+                Some('\\') => value.push(chars.next().ok_or_else(invalid)?.1),
+                Some(c) => value.push(c),
+                None => return Err(invalid()),
+            }
+        }
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            Some((index, ']')) => {
+                self.position += index + 1;
+                Ok(Token::Tag(name, value))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token, PgnError>;
+
+    fn next(&mut self) -> Option<Result<Token, PgnError>> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+            let at_line_start = self.text[..self.position]
+                .trim_end_matches([' ', '\t'])
+                .ends_with('\n')
+                || self.text[..self.position].trim().is_empty();
+            let rest = self.rest();
+            let first = rest.chars().next()?;
+            let token = match first {
+                '%' if at_line_start => {
+                    self.skip_line();
+                    continue;
+                }
+                '{' => match rest.find('}') {
+                    Some(end) => {
+                        self.position += end + 1;
+                        Token::Comment(rest[1..end].trim().to_string())
+                    }
+                    None => {
+                        self.position = self.text.len();
+                        return Some(Err(PgnError::UnterminatedComment));
+                    }
+                },
+                ';' => Token::Comment(self.skip_line()[1..].trim().to_string()),
+                '(' => {
+                    self.position += 1;
+                    Token::Open
+                }
+                ')' => {
+                    self.position += 1;
+                    Token::Close
+                }
+                '[' => {
+                    let tag = self.tag();
+                    if tag.is_err() {
+                        self.skip_line();
+                    }
+                    return Some(tag);
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "(){};[".contains(c))
+                        .unwrap_or(rest.len())
+                        .max(first.len_utf8());
+                    self.position += end;
+                    let word = &rest[..end];
+                    if let Some(nag) = word.strip_prefix('$') {
+                        match nag.parse() {
+                            Ok(nag) => Token::Nag(nag),
+                            Err(_) => return Some(Err(PgnError::UnexpectedToken(word.into()))),
+                        }
+                    } else if RESULTS.contains(&word) {
+                        Token::Result(word.to_string())
+                    } else {
+                        // Move numbers such as "12." or "12..." may run into the move
+                        let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                        let san = if digits.is_empty() || digits.starts_with('.') {
+                            digits.trim_start_matches('.')
+                        } else {
+                            word
+                        };
+                        if san.is_empty() {
+                            continue;
+                        }
+                        Token::Move(san.to_string())
+                    }
+                }
+            };
+            return Some(Ok(token));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Move;
+
+    #[test]
+    fn test_read_pgn() {
+        let pgn = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[White "Fischer, Robert J."]
+[Black "Spassky, \"Boris\" V."]
+[Result "1/2-1/2"]
+
+{Opening} 1. e4 e5 2. Nf3 $1 Nc6 (2... d6 {Philidor} 3. d4 (3. Bc4 Be7!?) exd4)
+(2... Nf6) 3.Bb5 ; the Ruy Lopez
+3... a6?! 1/2-1/2"#;
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        assert_eq!(tree.tags().len(), 5);
+        assert_eq!(tree.tag("Black"), Some("Spassky, \"Boris\" V."));
+        assert_eq!(tree.result(), "1/2-1/2");
+        let mainline: Vec<String> = tree.mainline().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(mainline, ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]);
+
+        assert_eq!(tree.root().comments(), ["Opening"]);
+        let e4 = &tree.root().variations()[0];
+        let nf3 = &e4.variations()[0].variations()[0];
+        assert_eq!(nf3.nags(), [1]);
+        // Black's second move has the main line and two variations
+        let replies: Vec<String> = nf3
+            .variations()
+            .iter()
+            .map(|node| node.mv().unwrap().to_string())
+            .collect();
+        assert_eq!(replies, ["b8c6", "d7d6", "g8f6"]);
+        let d6 = &nf3.variations()[1];
+        assert_eq!(d6.comments(), ["Philidor"]);
+        let bc4 = &d6.variations()[1];
+        assert_eq!(bc4.mv(), Some("f1c4".parse().unwrap()));
+        let be7 = &bc4.variations()[0];
+        assert_eq!(be7.nags(), [5]);
+        assert_eq!(be7.position().get_turn(), Color::White);
+
+        let bb5 = &nf3.variations()[0].variations()[0];
+        assert_eq!(bb5.comments(), ["the Ruy Lopez"]);
+        assert_eq!(bb5.variations()[0].nags(), [6]);
+    }
+
+    #[test]
+    fn test_read_pgn_from_fen() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 w Q - 0 1\"]\n\n1. O-O-O Kf7 *";
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        assert_eq!(tree.mainline().len(), 2);
+        assert_eq!(tree.result(), "*");
+        assert_eq!(tree.root().position().to_fen(), "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+
+        // Black to move starts with a move number followed by dots
+        let pgn = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n1... Kd7 2. Kd2";
+        assert_eq!(GameTree::from_pgn(pgn).unwrap().mainline().len(), 2);
+    }
+
+    #[test]
+    fn test_pgn_errors() {
+        let error = |pgn: &str| GameTree::from_pgn(pgn).err().unwrap();
+        assert!(matches!(
+            error("1. e4 e5 2. Ke3"),
+            PgnError::InvalidMove(san, ChessError::IllegalMove) if san == "Ke3"
+        ));
+        assert!(matches!(error("1. e4 {never closed"), PgnError::UnterminatedComment));
+        assert!(matches!(error("1. e4 (1. d4"), PgnError::UnbalancedVariation));
+        assert!(matches!(error("1. e4 ) e5"), PgnError::UnbalancedVariation));
+        assert!(matches!(error("( 1. e4 )"), PgnError::UnexpectedToken(_)));
+        assert!(matches!(error("1. e4 ( {empty} ) e5"), PgnError::UnexpectedToken(_)));
+        assert!(matches!(error("[Event \"unclosed]\n1. e4"), PgnError::InvalidTag(_)));
+        assert!(matches!(error("[FEN \"8/8 w - - 0 1\"]"), PgnError::InvalidFen(_)));
+        assert!(matches!(error("1. e4?!? e5"), PgnError::UnexpectedToken(_)));
+        assert!(matches!(
+            error("1. e4 e5 1-0 extra"),
+            PgnError::UnexpectedToken(token) if token == "extra"
+        ));
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let mut game = Game::new();
+        for san in ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"].iter() {
+            game.play_san(san).unwrap();
+        }
+        let tags = PgnTags {
+            white: "White \\ \"Player\"".to_string(),
+            ..PgnTags::default()
+        };
+        let pgn = game.to_pgn(&tags);
+        assert!(pgn.starts_with("[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n"));
+        assert!(pgn.contains("[White \"White \\\\ \\\"Player\\\"\"]\n"));
+        assert!(pgn.contains("[Result \"1-0\"]\n\n"));
+        assert!(!pgn.contains("FEN"));
+        assert!(pgn.ends_with("\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n"));
+        let tree = GameTree::from_pgn(&pgn).unwrap();
+        assert_eq!(tree.tag("White"), Some("White \\ \"Player\""));
+        let moves: Vec<Move> = game.history().iter().map(|entry| entry.mv).collect();
+        assert_eq!(tree.mainline(), moves);
+
+        // Games from a custom position keep it, and may start with Black
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12";
+        let mut game = Game::from_fen(fen).unwrap();
+        for san in ["Kd7", "e4", "Ke6"].iter() {
+            game.play_san(san).unwrap();
+        }
+        let pgn = game.to_pgn(&PgnTags::default());
+        assert!(pgn.contains("[Result \"*\"]\n[SetUp \"1\"]\n"));
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 12\"]\n"));
+        assert!(pgn.ends_with("\n12... Kd7 13. e4 Ke6 *\n"));
+
+        // Long games are wrapped, and read back the same
+        let mut game = Game::new();
+        for ply in 0..60 {
+            let moves = game.all_legal_moves();
+            if moves.is_empty() || game.is_game_over() {
+                break;
+            }
+            game.move_piece(moves[ply * 7 % moves.len()]).unwrap();
+        }
+        let pgn = game.to_pgn(&PgnTags::default());
+        assert!(pgn.lines().all(|line| line.len() <= LINE_WIDTH));
+        assert!(pgn.lines().filter(|line| !line.starts_with('[')).count() > 3);
+        let tree = GameTree::from_pgn(&pgn).unwrap();
+        assert_eq!(tree.mainline().len(), game.history().len());
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let pgn = "[Event \"Analysis\"]\n[Annotator \"Someone\"]\n\n\
+                   {Game comment} 1. e4 $1 {Best by test} 1... e5 (1... c5 2. Nf3 (2. c3 $6) \
+                   2... d6) ({Or} 1... e6 2. d4) 2. Nf3 Nc6 (2... Nf6 3. Nxe5) 3. Bb5 a6 4. Ba4 \
+                   Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 1-0\n";
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        let written = tree.to_pgn();
+        assert!(written.lines().all(|line| line.len() <= LINE_WIDTH));
+        let movetext = written.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(written.starts_with("[Event \"Analysis\"]\n[Annotator \"Someone\"]\n\n"));
+        assert!(movetext.contains(
+            "{Game comment} 1. e4 $1 {Best by test} 1... e5 (1... c5 2. Nf3 (2. c3 $6) 2... d6)"
+        ));
+        assert!(movetext.contains("({Or} 1... e6 2. d4) 2. Nf3 Nc6 (2... Nf6 3. Nxe5) 3. Bb5"));
+        assert!(written.ends_with("1-0\n"));
+
+        // Reading what was written gives the same tree
+        let read = GameTree::from_pgn(&written).unwrap();
+        assert_eq!(read.to_pgn(), written);
+        assert_eq!(read.mainline(), tree.mainline());
+        let e5 = &read.root().variations()[0].variations()[2];
+        assert_eq!(e5.starting_comments(), ["Or"]);
+
+        // Comments added to the tree read back the same
+        let mut tree = GameTree::from_pgn(pgn).unwrap();
+        tree.to_end();
+        assert!(tree.add_comment("see {this}\nnote").is_err());
+        assert_eq!(tree.add_comment("see {this) note "), Ok(()));
+        assert_eq!(tree.add_comment("see {this} note"), Ok(()));
+        let read = GameTree::from_pgn(&tree.to_pgn()).unwrap();
+        assert_eq!(read.to_pgn(), tree.to_pgn());
+        let mut node = read.root();
+        while let Some(next) = node.variations().first() {
+            node = next;
+        }
+        assert_eq!(node.comments(), ["see {this) note", "see {this} note"]);
+
+        // Comments holding a "}" are written to the end of the line
+        let pgn = "1. e4 ; a } b\ne5 (1... c5 ; c } d\n) (; e } f\n1... e6) *\n";
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        let written = tree.to_pgn();
+        assert_eq!(written, "1. e4 ; a } b\n1... e5 (1... c5 ; c } d\n) (; e } f\n1... e6) *\n");
+        let read = GameTree::from_pgn(&written).unwrap();
+        assert_eq!(read.to_pgn(), written);
+        assert_eq!(read.root().variations()[0].comments(), ["a } b"]);
+
+        // Trees built from a custom position write it as a FEN tag
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let mut tree = GameTree::new(Game::from_fen(fen).unwrap());
+        tree.play_san("Kd2").unwrap();
+        let written = tree.to_pgn();
+        assert_eq!(written, format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. Kd2 *\n", fen));
+        let read = GameTree::from_pgn(&written).unwrap();
+        assert_eq!(read.to_pgn(), written);
+        assert_eq!(read.root().position().to_fen(), fen);
+
+        // Games starting with Black number their first move
+        let pgn = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n\
+                   1... Kd7 (1... Kf7 2. e4) 2. Kd2 *\n";
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        assert_eq!(tree.to_pgn(), pgn);
+
+        // Suffix annotations are written as NAGs, and trees without tags start with moves
+        let tree = GameTree::from_pgn("1. f3? e5 2. g4?? Qh4# 0-1").unwrap();
+        assert_eq!(tree.to_pgn(), "1. f3 $2 e5 2. g4 $4 Qh4# 0-1\n");
+    }
+
+    #[test]
+    fn test_pgn_reader() {
+        let pgn = "[Event \"One\"]\n\n1. e4 {a [bracket]\n[in a comment]} e5 1-0\n\n\
+                   [Event \"Two\"]\n\n1. e5 0-1\n\n\
+                   % escaped line\n[Event \"Three\"]\n[Result \"*\"]\n\n1. d4\n";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes()).collect();
+        assert_eq!(games.len(), 3);
+
+        let first = games[0].as_ref().unwrap();
+        assert_eq!(first.tag("Event"), Some("One"));
+        assert_eq!(first.mainline().len(), 2);
+        assert_eq!(first.result(), "1-0");
+        assert_eq!(
+            first.root().variations()[0].comments(),
+            ["a [bracket]\n[in a comment]"]
+        );
+
+        // A bad game does not stop the games after it from being read
+        assert!(matches!(games[1], Err(PgnError::InvalidMove(..))));
+        let third = games[2].as_ref().unwrap();
+        assert_eq!(third.tag("Event"), Some("Three"));
+        assert_eq!(third.result(), "*");
+
+        // Games without tags are told apart by their result
+        let pgn = "1. e4 e5 1-0\n1. d4 {not the end 0-1\n} d5 ; 1-0\n2. c4 *\n\n1. c4 0-1\n";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes()).collect();
+        let lengths: Vec<usize> = games
+            .iter()
+            .map(|game| game.as_ref().unwrap().mainline().len())
+            .collect();
+        assert_eq!(lengths, [2, 3, 1]);
+    }
+}
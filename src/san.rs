@@ -0,0 +1,235 @@
+// Standard Algebraic Notation, such as "Nbd7", "exd5", "O-O" or "e8=Q+"
+use crate::{ChessError, Game, Move, PieceKind, Square};
+
+impl Game {
+    // Find the legal move a SAN string describes. Check and annotation marks at the end are
+    // not checked, and promotions may leave out the "="
+    pub fn parse_san(&self, san: &str) -> Result<Move, ChessError> {
+        let invalid = || ChessError::InvalidMove(san.to_string());
+        let notation = san.trim_end_matches(|c| "+#!?".contains(c));
+        let moves = self.get_all_valid_moves();
+
+        // Castling is written as the side the king castles to
+        let castling_file = match notation {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        if let Some(file) = castling_file {
+            return moves
+                .into_iter()
+                .find(|mv| self.is_castling(*mv) && mv.to.file() == file)
+                .ok_or(ChessError::IllegalMove);
+        }
+
+        // Pieces other than pawns start with their uppercase letter
+        let mut chars: Vec<char> = notation.chars().collect();
+        let kind = match chars.first().copied() {
+            Some(letter) if "KQRBN".contains(letter) => {
+                chars.remove(0);
+                PieceKind::from_char(letter).ok_or_else(invalid)?
+            }
+            _ => PieceKind::Pawn,
+        };
+
+        // Promotions end in the letter of the new piece
+        let promotion = match chars.last().copied().and_then(PieceKind::from_char) {
+            Some(promotion) if kind == PieceKind::Pawn && chars.len() > 2 => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                Some(promotion)
+            }
+            _ => None,
+        };
+
+        // The target square comes last, after the optional file and rank of the moving piece
+        // and the capture mark
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let target: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let target: Square = target.parse().map_err(|_| invalid())?;
+        let is_capture = chars.last() == Some(&'x');
+        if is_capture {
+            chars.pop();
+        }
+        let (from_file, from_rank) = match chars.as_slice() {
+            [] => (None, None),
+            [file @ 'a'..='h'] => (Some(*file as u8 - b'a'), None),
+            [rank @ '1'..='8'] => (None, Some(*rank as u8 - b'1')),
+            [file @ 'a'..='h', rank @ '1'..='8'] => {
+                (Some(*file as u8 - b'a'), Some(*rank as u8 - b'1'))
+            }
+            _ => return Err(invalid()),
+        };
+
+        let mut matching = moves.into_iter().filter(|mv| {
+            self.piece_at(mv.from).map(|piece| piece.kind) == Some(kind)
+                && mv.to == target
+                && mv.promotion == promotion
+                && from_file.is_none_or(|file| mv.from.file() == file)
+                && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+                && !self.is_castling(*mv)
+                // A capture mark must be on a move that captures
+                && (!is_capture
+                    || self.piece_at(mv.to).is_some()
+                    || self.is_enpassant_capture(mv.from.index(), mv.to.index()))
+        });
+        match (matching.next(), matching.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(ChessError::AmbiguousMove(san.to_string())),
+            (None, _) => Err(ChessError::IllegalMove),
+        }
+    }
+
+    // Play the move a SAN string describes, returning it
+    pub fn play_san(&mut self, san: &str) -> Result<Move, ChessError> {
+        let mv = self.parse_san(san)?;
+        self.move_piece(mv)?;
+        Ok(mv)
+    }
+
+    // Write a legal move in SAN, naming the file or rank of the moving piece only when another
+    // piece of the same kind could move to the same square
+    pub fn to_san(&self, mv: Move) -> Result<String, ChessError> {
+        let moves = self.get_all_valid_moves();
+        if !moves.contains(&mv) {
+            return Err(ChessError::IllegalMove);
+        }
+        let piece = self.piece_at(mv.from).ok_or(ChessError::NoPieceOnSquare)?;
+
+        let mut san = String::new();
+        if self.is_castling(mv) {
+            san.push_str(if mv.to.file() == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            let is_capture = self.piece_at(mv.to).is_some()
+                || piece.kind == PieceKind::Pawn && self.enpassant == Some(mv.to);
+            if piece.kind == PieceKind::Pawn {
+                if is_capture {
+                    san.push((b'a' + mv.from.file()) as char);
+                }
+            } else {
+                san.push(piece.kind.to_char());
+                let others: Vec<Square> = moves
+                    .iter()
+                    .filter(|other| {
+                        other.to == mv.to
+                            && other.from != mv.from
+                            && self.piece_at(other.from) == Some(piece)
+                    })
+                    .map(|other| other.from)
+                    .collect();
+                if !others.is_empty() {
+                    let file = (b'a' + mv.from.file()) as char;
+                    let rank = (b'1' + mv.from.rank()) as char;
+                    if others.iter().all(|other| other.file() != mv.from.file()) {
+                        san.push(file);
+                    } else if others.iter().all(|other| other.rank() != mv.from.rank()) {
+                        san.push(rank);
+                    } else {
+                        san.push(file);
+                        san.push(rank);
+                    }
+                }
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&mv.to.to_string());
+            if let Some(kind) = mv.promotion {
+                san.push('=');
+                san.push(kind.to_char());
+            }
+        }
+
+        // Mark moves that give check, or checkmate when nothing can answer them
+        let mut after = self.clone();
+        after.make_move(mv);
+        if after.is_in_check(after.turn) {
+            san.push(if after.get_all_valid_moves().is_empty() { '#' } else { '+' });
+        }
+        Ok(san)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_san() {
+        let mut game = Game::new();
+        for san in ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"].iter() {
+            assert!(game.play_san(san).is_ok(), "{}", san);
+        }
+        assert_eq!(game.parse_san("Ne7"), Ok("g8e7".parse().unwrap()));
+        assert_eq!(game.parse_san("Nbd7"), Err(ChessError::IllegalMove));
+        assert_eq!(game.parse_san("Qd3"), Ok("d8d3".parse().unwrap()));
+        assert_eq!(game.parse_san("Bg4!?"), Ok("c8g4".parse().unwrap()));
+        assert_eq!(game.parse_san("Qxd3"), Err(ChessError::IllegalMove));
+        assert_eq!(game.parse_san("Qxd2"), Ok("d8d2".parse().unwrap()));
+        assert_eq!(game.parse_san("Zz9"), Err(ChessError::InvalidMove("Zz9".to_string())));
+        assert_eq!(game.parse_san(""), Err(ChessError::InvalidMove("".to_string())));
+
+        // A pawn or piece marked as capturing must capture, en passant counting
+        let game = Game::new();
+        assert_eq!(game.parse_san("xe4"), Err(ChessError::IllegalMove));
+        assert_eq!(game.parse_san("Nxf3"), Err(ChessError::IllegalMove));
+        let game = Game::from_fen("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1").unwrap();
+        assert_eq!(game.parse_san("dxe6"), Ok("d5e6".parse().unwrap()));
+
+        // Two knights can reach d6, so the file or rank must be given
+        let game = Game::from_fen("4k3/8/8/1N3N2/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.parse_san("Nd6+"), Err(ChessError::AmbiguousMove("Nd6+".to_string())));
+        assert_eq!(game.parse_san("Nbd6+"), Ok("b5d6".parse().unwrap()));
+        assert_eq!(game.parse_san("Nf5d6"), Ok("f5d6".parse().unwrap()));
+
+        // Promotions with and without "="
+        let game = Game::from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.parse_san("a8=Q+"), Ok("a7a8q".parse().unwrap()));
+        assert_eq!(game.parse_san("axb8N"), Ok("a7b8n".parse().unwrap()));
+        assert_eq!(game.parse_san("a8"), Err(ChessError::IllegalMove));
+
+        // Castling queenside, also written with zeros
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(game.parse_san("O-O-O"), Ok("e1c1".parse().unwrap()));
+        assert_eq!(game.parse_san("0-0"), Ok("e1g1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_to_san() {
+        let game = Game::from_fen("r3k2r/8/8/1N3N2/8/8/4P3/R3K2R w KQkq - 0 1").unwrap();
+        let san = |mv: &str| game.to_san(mv.parse().unwrap()).unwrap();
+        assert_eq!(san("e2e4"), "e4");
+        assert_eq!(san("b5d6"), "Nbd6+");
+        assert_eq!(san("e1c1"), "O-O-O");
+        assert_eq!(san("a1a8"), "Rxa8+");
+        assert_eq!(game.to_san("e2e5".parse().unwrap()), Err(ChessError::IllegalMove));
+
+        // Rooks on the same file are told apart by their rank
+        let game = Game::from_fen("R7/4k3/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(game.to_san("a1a4".parse().unwrap()).unwrap(), "R1a4");
+
+        // Three queens may need both file and rank
+        let game = Game::from_fen("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1").unwrap();
+        assert_eq!(game.to_san("h4e1".parse().unwrap()).unwrap(), "Qh4e1");
+
+        // En passant, promotion and mate
+        let game = Game::from_fen("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1").unwrap();
+        assert_eq!(game.to_san("d5e6".parse().unwrap()).unwrap(), "dxe6");
+        let game = Game::from_fen("k7/2P5/1K6/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.to_san("c7c8q".parse().unwrap()).unwrap(), "c8=Q#");
+        assert_eq!(game.to_san("c7c8n".parse().unwrap()).unwrap(), "c8=N");
+
+        // Every legal move reads back as itself
+        let game = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        for mv in game.get_all_valid_moves() {
+            assert_eq!(game.parse_san(&game.to_san(mv).unwrap()), Ok(mv));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+// Games with variations and annotations, for analysis
+use crate::pgn::RESULTS;
+use crate::{ChessError, Game, Move};
+
+// A game as a tree of moves. The root holds the starting position, and every other node holds
+// a move and the position after it. The first child of a node continues the main line and the
+// others are variations. A cursor points at one node, where moves are played and annotations
+// are added
+#[derive(Clone)]
+pub struct GameTree {
+    tags: Vec<(String, String)>,
+    root: GameNode,
+    result: String,
+    // Indices of the variations leading from the root to the node of the cursor
+    cursor: Vec<usize>,
+}
+
+#[derive(Clone)]
+pub struct GameNode {
+    position: Game,
+    mv: Option<Move>,
+    // Comments written before the move, which PGN allows at the start of a variation
+    starting_comments: Vec<String>,
+    comments: Vec<String>,
+    nags: Vec<u8>,
+    children: Vec<GameNode>,
+}
+
+impl GameTree {
+    // Create a tree without moves that starts from a position
+    pub fn new(position: Game) -> GameTree {
+        GameTree {
+            tags: Vec::new(),
+            root: GameNode::new(position, None),
+            result: "*".to_string(),
+            cursor: Vec::new(),
+        }
+    }
+
+    // Get the tag pairs in the order they were given
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    // Get the value of a tag
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Set the value of a tag, adding it after the others if it is new
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // Get the result token, such as "1-0" or "*" for a game still going on
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    // Set the result token, which must be one PGN allows
+    pub fn set_result(&mut self, result: &str) -> Result<(), ChessError> {
+        if !RESULTS.contains(&result) {
+            return Err(ChessError::InvalidResult(result.to_string()));
+        }
+        self.result = result.to_string();
+        Ok(())
+    }
+
+    pub fn root(&self) -> &GameNode {
+        &self.root
+    }
+
+    pub(crate) fn root_mut(&mut self) -> &mut GameNode {
+        &mut self.root
+    }
+
+    // Get the moves of the main line
+    pub fn mainline(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut node = &self.root;
+        while let Some(next) = node.children.first() {
+            moves.extend(next.mv);
+            node = next;
+        }
+        moves
+    }
+
+    // Get the node the cursor points at
+    pub fn current(&self) -> &GameNode {
+        self.cursor
+            .iter()
+            .fold(&self.root, |node, index| &node.children[*index])
+    }
+
+    fn current_mut(&mut self) -> &mut GameNode {
+        let mut node = &mut self.root;
+        for index in self.cursor.iter() {
+            node = &mut node.children[*index];
+        }
+        node
+    }
+
+    // Get the moves leading from the start to the cursor
+    pub fn moves_to_current(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut node = &self.root;
+        for index in self.cursor.iter() {
+            node = &node.children[*index];
+            moves.extend(node.mv);
+        }
+        moves
+    }
+
+    // Move the cursor along the main line of the current node, returning false at the end
+    pub fn forward(&mut self) -> bool {
+        self.enter_variation(0)
+    }
+
+    // Move the cursor to the position before the current move, returning false at the start
+    pub fn back(&mut self) -> bool {
+        self.cursor.pop().is_some()
+    }
+
+    // Move the cursor into a variation of the current node, 0 being the main line
+    pub fn enter_variation(&mut self, index: usize) -> bool {
+        if index < self.current().children.len() {
+            self.cursor.push(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn to_start(&mut self) {
+        self.cursor.clear();
+    }
+
+    // Follow the main line from the cursor to its last move
+    pub fn to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    // Play a move from the cursor and move the cursor to it. A move already in the tree is
+    // followed, and a new one is added as the last variation
+    pub fn play(&mut self, mv: Move) -> Result<(), ChessError> {
+        let node = self.current_mut();
+        let mut position = node.position.clone();
+        position.move_piece(mv)?;
+        // Promotions left open become queens
+        let mv = position.history.last().expect("a move was played").mv;
+        let index = match node.children.iter().position(|child| child.mv == Some(mv)) {
+            Some(index) => index,
+            None => {
+                node.add_variation(position, mv);
+                node.children.len() - 1
+            }
+        };
+        self.cursor.push(index);
+        Ok(())
+    }
+
+    // Play a move written in SAN from the cursor, returning it
+    pub fn play_san(&mut self, san: &str) -> Result<Move, ChessError> {
+        let mv = self.current().position.parse_san(san)?;
+        self.play(mv)?;
+        Ok(mv)
+    }
+
+    // Add a comment to the move of the cursor. PGN writes a comment holding a "}" to the end
+    // of the line, so such comments may not hold line breaks. Space around a comment is not
+    // kept, as in PGN
+    pub fn add_comment(&mut self, comment: &str) -> Result<(), ChessError> {
+        let comment = comment.trim();
+        if comment.contains('}') && comment.contains(['\n', '\r']) {
+            return Err(ChessError::InvalidComment(comment.to_string()));
+        }
+        self.current_mut().comments.push(comment.to_string());
+        Ok(())
+    }
+
+    pub fn add_nag(&mut self, nag: u8) {
+        self.current_mut().nags.push(nag);
+    }
+
+    // Make the line of the cursor the main line, by making each move on the way the first of
+    // its variations. The other variations keep their order
+    pub fn promote_to_mainline(&mut self) {
+        let mut node = &mut self.root;
+        for index in self.cursor.iter_mut() {
+            let child = node.children.remove(*index);
+            node.children.insert(0, child);
+            *index = 0;
+            node = &mut node.children[0];
+        }
+    }
+
+    // Delete the move of the cursor with every move after it, moving the cursor to the
+    // position before it. Returns false at the start, which can not be deleted
+    pub fn delete_variation(&mut self) -> bool {
+        match self.cursor.pop() {
+            Some(index) => {
+                self.current_mut().children.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl GameNode {
+    fn new(position: Game, mv: Option<Move>) -> GameNode {
+        GameNode {
+            position,
+            mv,
+            starting_comments: Vec::new(),
+            comments: Vec::new(),
+            nags: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    // Get the position after the move of the node
+    pub fn position(&self) -> &Game {
+        &self.position
+    }
+
+    // Get the move leading to the node, which the root does not have
+    pub fn mv(&self) -> Option<Move> {
+        self.mv
+    }
+
+    pub fn starting_comments(&self) -> &[String] {
+        &self.starting_comments
+    }
+
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    // Get the Numeric Annotation Glyphs of the move, such as 1 for a good move
+    pub fn nags(&self) -> &[u8] {
+        &self.nags
+    }
+
+    // Get the moves played from this position, the main line first
+    pub fn variations(&self) -> &[GameNode] {
+        &self.children
+    }
+
+    // Play a move from the position of the node and add it as the last variation
+    pub(crate) fn add_variation(&mut self, position: Game, mv: Move) -> &mut GameNode {
+        self.children.push(GameNode::new(position, Some(mv)));
+        let last = self.children.len() - 1;
+        &mut self.children[last]
+    }
+
+    pub(crate) fn children_mut(&mut self) -> &mut Vec<GameNode> {
+        &mut self.children
+    }
+
+    pub(crate) fn starting_comments_mut(&mut self) -> &mut Vec<String> {
+        &mut self.starting_comments
+    }
+
+    pub(crate) fn comments_mut(&mut self) -> &mut Vec<String> {
+        &mut self.comments
+    }
+
+    pub(crate) fn nags_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.nags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    fn san_line(tree: &GameTree) -> Vec<String> {
+        tree.mainline().iter().map(|mv| mv.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut tree = GameTree::new(Game::new());
+        for san in ["e4", "e5", "Nf3"].iter() {
+            tree.play_san(san).unwrap();
+        }
+        assert_eq!(tree.current().position().get_turn(), Color::Black);
+        assert_eq!(tree.moves_to_current().len(), 3);
+        assert!(!tree.forward());
+
+        // Playing a move already in the tree follows it instead of adding it again
+        tree.to_start();
+        assert!(tree.current().mv().is_none());
+        assert!(!tree.back());
+        tree.play("e2e4".parse().unwrap()).unwrap();
+        assert_eq!(tree.root().variations().len(), 1);
+
+        // A different move becomes a variation, and the cursor follows it
+        tree.play_san("c5").unwrap();
+        assert_eq!(tree.add_comment(" Sicilian "), Ok(()));
+        assert_eq!(
+            tree.add_comment("see {this}\nnote"),
+            Err(ChessError::InvalidComment("see {this}\nnote".to_string()))
+        );
+        tree.add_nag(5);
+        assert!(tree.back());
+        assert_eq!(tree.current().variations().len(), 2);
+        assert!(tree.enter_variation(1));
+        assert_eq!(tree.current().comments(), ["Sicilian"]);
+        assert_eq!(tree.current().nags(), [5]);
+        assert!(!tree.enter_variation(0));
+        assert_eq!(tree.play_san("Ke3"), Err(ChessError::IllegalMove));
+
+        tree.to_start();
+        tree.to_end();
+        assert_eq!(tree.moves_to_current(), tree.mainline());
+
+        // Pawns promote to a queen when no piece is chosen, like in Game
+        let mut tree = GameTree::new(Game::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap());
+        tree.play("a7a8".parse().unwrap()).unwrap();
+        tree.back();
+        tree.play("a7a8q".parse().unwrap()).unwrap();
+        assert_eq!(tree.root().variations().len(), 1);
+    }
+
+    #[test]
+    fn test_set_result() {
+        let mut tree = GameTree::new(Game::new());
+        assert_eq!(tree.set_result("1/2-1/2"), Ok(()));
+        assert_eq!(tree.result(), "1/2-1/2");
+        assert_eq!(tree.set_result("foo"), Err(ChessError::InvalidResult("foo".to_string())));
+        assert_eq!(tree.result(), "1/2-1/2");
+    }
+
+    #[test]
+    fn test_edit_variations() {
+        let mut tree = GameTree::new(Game::new());
+        for san in ["e4", "e5", "Nf3"].iter() {
+            tree.play_san(san).unwrap();
+        }
+        tree.to_start();
+        tree.forward();
+        for san in ["c5", "Nf3", "d6"].iter() {
+            tree.play_san(san).unwrap();
+        }
+        tree.back();
+        tree.play_san("Nc6").unwrap();
+
+        // The Sicilian with Nc6 becomes the main line, keeping the other moves as variations
+        tree.promote_to_mainline();
+        assert_eq!(san_line(&tree), ["e2e4", "c7c5", "g1f3", "b8c6"]);
+        assert_eq!(tree.moves_to_current(), tree.mainline());
+        let e4 = &tree.root().variations()[0];
+        assert_eq!(e4.variations()[1].mv(), Some("e7e5".parse().unwrap()));
+        let nf3 = &e4.variations()[0].variations()[0];
+        assert_eq!(nf3.variations()[1].mv(), Some("d7d6".parse().unwrap()));
+
+        // Deleting a move removes the moves after it and leaves the cursor before it
+        tree.to_start();
+        tree.forward();
+        assert!(tree.enter_variation(1));
+        assert!(tree.delete_variation());
+        assert_eq!(tree.current().variations().len(), 1);
+        assert_eq!(tree.moves_to_current().len(), 1);
+        tree.to_start();
+        assert!(!tree.delete_variation());
+        tree.forward();
+        assert!(tree.delete_variation());
+        assert!(tree.mainline().is_empty());
+    }
+}
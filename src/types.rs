@@ -0,0 +1,434 @@
+// Value types used by the public Game API
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::ChessError;
+
+// Color of a player or a piece
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    // Get the color of the other player
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    // Position of the color's bitboards in the board, white first
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Color::White => 0,
+            Color::Black => 1,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::White => write!(f, "white"),
+            Color::Black => write!(f, "black"),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ChessError;
+
+    // Accepts both FEN letters and full names
+    fn from_str(s: &str) -> Result<Color, ChessError> {
+        match s.to_ascii_lowercase().as_str() {
+            "w" | "white" => Ok(Color::White),
+            "b" | "black" => Ok(Color::Black),
+            _ => Err(ChessError::InvalidColor(s.to_string())),
+        }
+    }
+}
+
+// Kind of a piece regardless of its color
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PieceKind {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceKind {
+    // Every kind, in the order of their bitboards in the board
+    pub(crate) const ALL: [PieceKind; 6] = [
+        PieceKind::King,
+        PieceKind::Queen,
+        PieceKind::Rook,
+        PieceKind::Bishop,
+        PieceKind::Knight,
+        PieceKind::Pawn,
+    ];
+
+    // Position of the kind's bitboard in the board
+    pub(crate) fn index(self) -> usize {
+        match self {
+            PieceKind::King => 0,
+            PieceKind::Queen => 1,
+            PieceKind::Rook => 2,
+            PieceKind::Bishop => 3,
+            PieceKind::Knight => 4,
+            PieceKind::Pawn => 5,
+        }
+    }
+
+    // Get kind from its letter in either case
+    pub fn from_char(letter: char) -> Option<PieceKind> {
+        match letter.to_ascii_uppercase() {
+            'K' => Some(PieceKind::King),
+            'Q' => Some(PieceKind::Queen),
+            'R' => Some(PieceKind::Rook),
+            'B' => Some(PieceKind::Bishop),
+            'N' => Some(PieceKind::Knight),
+            'P' => Some(PieceKind::Pawn),
+            _ => None,
+        }
+    }
+
+    // Get the uppercase letter of the kind
+    pub fn to_char(self) -> char {
+        match self {
+            PieceKind::King => 'K',
+            PieceKind::Queen => 'Q',
+            PieceKind::Rook => 'R',
+            PieceKind::Bishop => 'B',
+            PieceKind::Knight => 'N',
+            PieceKind::Pawn => 'P',
+        }
+    }
+}
+
+impl fmt::Display for PieceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl FromStr for PieceKind {
+    type Err = ChessError;
+
+    fn from_str(s: &str) -> Result<PieceKind, ChessError> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(PieceKind::from_char), chars.next()) {
+            (Some(kind), None) => Ok(kind),
+            _ => Err(ChessError::InvalidPiece(s.to_string())),
+        }
+    }
+}
+
+// A piece of a given color
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceKind) -> Piece {
+        Piece { color, kind }
+    }
+
+    // Get piece from its FEN letter, uppercase being white
+    pub fn from_char(letter: char) -> Option<Piece> {
+        let color = if letter.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        PieceKind::from_char(letter).map(|kind| Piece { color, kind })
+    }
+
+    // Get the FEN letter of the piece
+    pub fn to_char(self) -> char {
+        match self.color {
+            Color::White => self.kind.to_char(),
+            Color::Black => self.kind.to_char().to_ascii_lowercase(),
+        }
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl FromStr for Piece {
+    type Err = ChessError;
+
+    fn from_str(s: &str) -> Result<Piece, ChessError> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Piece::from_char), chars.next()) {
+            (Some(piece), None) => Ok(piece),
+            _ => Err(ChessError::InvalidPiece(s.to_string())),
+        }
+    }
+}
+
+// A tile on the board, written in algebraic notation as a file letter followed by a rank
+// number, such as "e4"
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Square(pub(crate) u8);
+
+impl Square {
+    // Get square from a file (0 for a) and a rank (0 for rank 1)
+    pub fn new(file: u8, rank: u8) -> Option<Square> {
+        if file < 8 && rank < 8 {
+            // Tiles are numbered from a8 to h1
+            Some(Square((7 - rank) * 8 + file))
+        } else {
+            None
+        }
+    }
+
+    // Get the file of the square, 0 being the a-file
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    // Get the rank of the square, 0 being rank 1
+    pub fn rank(self) -> u8 {
+        7 - self.0 / 8
+    }
+
+    // Get the number of the tile, which is also its bit in a bitboard
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = (b'a' + self.file()) as char;
+        let rank = (b'1' + self.rank()) as char;
+        write!(f, "{}{}", file, rank)
+    }
+}
+
+impl FromStr for Square {
+    type Err = ChessError;
+
+    // Files are accepted in either case
+    fn from_str(s: &str) -> Result<Square, ChessError> {
+        let mut chars = s.chars();
+        let file = match chars.next().map(|c| c.to_ascii_lowercase()) {
+            Some(c @ 'a'..='h') => c as u8 - b'a',
+            _ => return Err(ChessError::InvalidSquare(s.to_string())),
+        };
+        let rank = match chars.next() {
+            Some(c @ '1'..='8') => c as u8 - b'1',
+            _ => return Err(ChessError::InvalidSquare(s.to_string())),
+        };
+        if chars.next().is_some() {
+            return Err(ChessError::InvalidSquare(s.to_string()));
+        }
+        Ok(Square((7 - rank) * 8 + file))
+    }
+}
+
+// A move of the piece on one square to another square, with the piece a pawn promotes to
+// when it reaches the last rank. Moves are equal when they go between the same squares with
+// the same promotion, whatever their flags say
+#[derive(Copy, Clone, Debug)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceKind>,
+    pub flags: MoveFlags,
+}
+
+impl PartialEq for Move {
+    fn eq(&self, other: &Move) -> bool {
+        (self.from, self.to, self.promotion) == (other.from, other.to, other.promotion)
+    }
+}
+
+impl Eq for Move {}
+
+impl Hash for Move {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.from, self.to, self.promotion).hash(state);
+    }
+}
+
+// What a move does besides moving its piece. Only the moves listed by a game have their
+// flags filled in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MoveFlags {
+    pub capture: bool,
+    pub check: bool,
+    pub castle: bool,
+    pub en_passant: bool,
+    pub promotion: bool,
+}
+
+impl Move {
+    pub fn new(from: Square, to: Square) -> Move {
+        Move {
+            from,
+            to,
+            promotion: None,
+            flags: MoveFlags::default(),
+        }
+    }
+
+    pub fn new_promotion(from: Square, to: Square, kind: PieceKind) -> Move {
+        Move {
+            from,
+            to,
+            promotion: Some(kind),
+            flags: MoveFlags::default(),
+        }
+    }
+
+    // Read a move in the long algebraic notation of the Universal Chess Interface, where
+    // castling is the king moving two tiles, such as "e1g1", and promotions end in a
+    // lowercase letter, such as "e7e8q". Unlike parsing, only lowercase is allowed
+    pub fn from_uci(uci: &str) -> Result<Move, ChessError> {
+        let is_uci = match uci.as_bytes() {
+            [from_file, from_rank, to_file, to_rank, promotion @ ..] => {
+                (b'a'..=b'h').contains(from_file)
+                    && (b'1'..=b'8').contains(from_rank)
+                    && (b'a'..=b'h').contains(to_file)
+                    && (b'1'..=b'8').contains(to_rank)
+                    && matches!(promotion, [] | [b'q' | b'r' | b'b' | b'n'])
+            }
+            _ => false,
+        };
+        if !is_uci {
+            return Err(ChessError::InvalidMove(uci.to_string()));
+        }
+        uci.parse()
+    }
+
+    // Write the move in the long algebraic notation of the Universal Chess Interface
+    pub fn to_uci(self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(kind) = self.promotion {
+            write!(f, "{}", kind.to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Move {
+    type Err = ChessError;
+
+    // Moves are written as the two squares after each other, such as "e2e4", followed by
+    // the letter of the promotion piece, such as "e7e8q"
+    fn from_str(s: &str) -> Result<Move, ChessError> {
+        if !s.is_char_boundary(2) || !s.is_char_boundary(4) {
+            return Err(ChessError::InvalidMove(s.to_string()));
+        }
+        let promotion = match s.len() {
+            4 => None,
+            5 => match PieceKind::from_char(s[4..].chars().next().unwrap_or(' ')) {
+                Some(kind) => Some(kind),
+                None => return Err(ChessError::InvalidMove(s.to_string())),
+            },
+            _ => return Err(ChessError::InvalidMove(s.to_string())),
+        };
+        Ok(Move {
+            from: s[..2].parse()?,
+            to: s[2..4].parse()?,
+            promotion,
+            flags: MoveFlags::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piece_letters() {
+        for letter in "KQRBNPkqrbnp".chars() {
+            let piece = Piece::from_char(letter).unwrap();
+            assert_eq!(piece.to_char(), letter);
+            assert_eq!(PieceKind::ALL[piece.kind.index()], piece.kind);
+        }
+        assert_eq!(Piece::from_char('x'), None);
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("w".parse::<Color>(), Ok(Color::White));
+        assert_eq!("Black".parse::<Color>(), Ok(Color::Black));
+        assert_eq!("q".parse::<PieceKind>(), Ok(PieceKind::Queen));
+        assert_eq!(
+            "Qx".parse::<PieceKind>(),
+            Err(ChessError::InvalidPiece("Qx".to_string()))
+        );
+        assert_eq!("n".parse::<Piece>(), Ok(Piece::new(Color::Black, PieceKind::Knight)));
+        let mv: Move = "e2E4".parse().unwrap();
+        assert_eq!(mv.to_string(), "e2e4");
+        let mv: Move = "e7e8N".parse().unwrap();
+        assert_eq!(mv.promotion, Some(PieceKind::Knight));
+        assert_eq!(mv.to_string(), "e7e8n");
+        assert_eq!(
+            "e7e8x".parse::<Move>(),
+            Err(ChessError::InvalidMove("e7e8x".to_string()))
+        );
+        assert_eq!(
+            "e2e".parse::<Move>(),
+            Err(ChessError::InvalidMove("e2e".to_string()))
+        );
+        assert_eq!(
+            "e2e9".parse::<Move>(),
+            Err(ChessError::InvalidSquare("e9".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_uci() {
+        let mv = Move::from_uci("e7e8q").unwrap();
+        assert_eq!(mv.promotion, Some(PieceKind::Queen));
+        assert_eq!(mv.to_uci(), "e7e8q");
+        assert_eq!(Move::from_uci("e1g1").unwrap().to_uci(), "e1g1");
+        assert_eq!(
+            Move::from_uci("e7e8Q"),
+            Err(ChessError::InvalidMove("e7e8Q".to_string()))
+        );
+        // UCI's null move is not a move of a piece
+        assert_eq!(Move::from_uci("0000"), Err(ChessError::InvalidMove("0000".to_string())));
+        // Coordinates are lowercase, and pawns only promote to the pieces they may become
+        for uci in ["E2E4", "e2E4", "e7e8k", "e7e8p", "e2e4 ", "e2", "i2i4"].iter() {
+            assert_eq!(Move::from_uci(uci), Err(ChessError::InvalidMove(uci.to_string())));
+        }
+        assert_eq!("E2E4".parse::<Move>(), Move::from_uci("e2e4"));
+    }
+
+    #[test]
+    fn test_square_coordinates() {
+        let square: Square = "e4".parse().unwrap();
+        assert_eq!((square.file(), square.rank()), (4, 3));
+        assert_eq!(Square::new(4, 3), Some(square));
+        assert_eq!("E4".parse::<Square>(), Ok(square));
+        assert_eq!(Square::new(0, 0).unwrap().to_string(), "a1");
+        assert_eq!(Square::new(7, 7).unwrap().to_string(), "h8");
+        assert_eq!(Square::new(8, 0), None);
+        assert_eq!("i1".parse::<Square>(), Err(ChessError::InvalidSquare("i1".to_string())));
+        assert_eq!("a0".parse::<Square>(), Err(ChessError::InvalidSquare("a0".to_string())));
+    }
+}